@@ -1,4 +1,4 @@
-use std::time::Instant;
+use std::{env, time::Instant};
 
 use nom::{
     IResult,
@@ -13,7 +13,7 @@ use nom::{
 use aoc2023::read_input;
 
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 struct Set {
     red: u32,
     green: u32,
@@ -59,22 +59,112 @@ impl Set {
     pub fn power(self) -> u32 {
         self.red * self.green * self.blue
     }
+
+    pub fn total(self) -> u32 {
+        self.red + self.green + self.blue
+    }
+}
+
+/// Table of `ln(n!)` so hypergeometric probabilities can be summed in log space without overflow.
+struct LnFactorials {
+    table: Vec<f64>,
+}
+
+impl LnFactorials {
+    pub fn up_to(max: u32) -> LnFactorials {
+        let mut table = Vec::with_capacity(max as usize + 1);
+        table.push(0.0);
+        for n in 1..=max {
+            table.push(table[n as usize - 1] + (n as f64).ln());
+        }
+        LnFactorials { table }
+    }
+
+    pub fn ln_choose(&self, n: u32, k: u32) -> f64 {
+        self.table[n as usize] - self.table[k as usize] - self.table[(n - k) as usize]
+    }
+}
+
+/// Every way of splitting `total` cubes between the three colours with at least `lower_bound` of
+/// each.
+fn splits(total: u32, lower_bound: Set) -> impl Iterator<Item = Set> {
+    (lower_bound.red..=total).flat_map(move |red| {
+        (lower_bound.green..=total - red).filter_map(move |green| {
+            let blue = total - red - green;
+            (blue >= lower_bound.blue).then_some(Set { red, green, blue })
+        })
+    })
+}
+
+/// Finds the split of a bag holding exactly `total` cubes that maximizes the joint likelihood of
+/// all games. The total is taken as given rather than estimated: once the bag is much larger than
+/// the draws the likelihood is nearly flat in the total, and on the puzzle's games it keeps rising
+/// as far as any search goes, so a search bounded by some limit just returns that limit. Returns
+/// `None` if no bag of that size can produce every game.
+fn estimate_bag(games: &[Game], total: u32) -> Option<(Set, f64)> {
+    let lower_bound = games.iter().map(Game::min_set).reduce(Set::min_set)?;
+    let ln_fact = LnFactorials::up_to(total);
+    let mut best: Option<(Set, f64)> = None;
+    for bag in splits(total, lower_bound) {
+        let log_likelihood: f64 = games.iter().map(|g| g.log_likelihood(bag, &ln_fact)).sum();
+        if !best.is_some_and(|(_, best_ll)| best_ll >= log_likelihood) {
+            best = Some((bag, log_likelihood));
+        }
+    }
+    best
 }
 
 impl Game {
     pub fn is_possible(&self) -> bool {
+        self.is_possible_with(Set { red: 12, green: 13, blue: 14 })
+    }
+
+    pub fn is_possible_with(&self, bag: Set) -> bool {
         for set in self.sets.iter() {
-            if set.red > 12 {
+            if set.red > bag.red {
                 return false;
-            } else if set.green > 13 {
+            } else if set.green > bag.green {
                 return false;
-            } else if set.blue > 14 {
+            } else if set.blue > bag.blue {
                 return false;
             }
         }
         true
     }
 
+    /// Log-likelihood of observing every set in this game from `bag`, treating each set as a draw
+    /// without replacement (multivariate hypergeometric) with the cubes returned between sets.
+    pub fn log_likelihood(&self, bag: Set, ln_fact: &LnFactorials) -> f64 {
+        let mut total = 0.0;
+        for set in self.sets.iter() {
+            if set.red > bag.red || set.green > bag.green || set.blue > bag.blue {
+                return f64::NEG_INFINITY;
+            }
+            total += ln_fact.ln_choose(bag.red, set.red)
+                + ln_fact.ln_choose(bag.green, set.green)
+                + ln_fact.ln_choose(bag.blue, set.blue)
+                - ln_fact.ln_choose(bag.total(), set.total());
+        }
+        total
+    }
+
+    /// Posterior probability that this game was played with `bag` rather than any other split of
+    /// the same number of cubes, assuming every split was equally likely beforehand. Whether the
+    /// game is possible under `bag` needs no probability, since `is_possible_with` answers it
+    /// exactly; this says how plausible `bag` is given what the game showed, and is zero exactly
+    /// when the game is impossible under `bag`.
+    pub fn bag_posterior(&self, bag: Set, ln_fact: &LnFactorials) -> f64 {
+        let log_likelihood = self.log_likelihood(bag, ln_fact);
+        if log_likelihood == f64::NEG_INFINITY {
+            return 0.0;
+        }
+        // normalize in log space, relative to `bag` so the largest terms don't overflow
+        let evidence: f64 = splits(bag.total(), Set::default())
+            .map(|split| (self.log_likelihood(split, ln_fact) - log_likelihood).exp())
+            .sum();
+        1.0 / evidence
+    }
+
     pub fn from_line(line: &str) -> IResult<&str, Game> {
         let (i, (_, id, _, sets)) = tuple((
             tag("Game "),
@@ -102,11 +192,66 @@ pub fn main() {
     let input = read_input("02");
     let start_compute_time = Instant::now();
     let mut sum = 0;
+    let mut games = Vec::new();
     for line in input.lines() {
         let (_, game) = Game::from_line(line).unwrap();
         sum += game.min_set().power();
+        games.push(game);
     }
     println!("Total time: {:?}, compute time: {:?}", start_time.elapsed(), start_compute_time.elapsed());
     println!("{}", sum);
 
+    // estimating the bag needs its total number of cubes, given as the first argument
+    let Some(total) = env::args().nth(1).and_then(|arg| arg.parse().ok()) else {
+        return;
+    };
+    match estimate_bag(&games, total) {
+        Some((bag, log_likelihood)) => {
+            println!("Most likely bag: {:?} (log-likelihood {:.3})", bag, log_likelihood);
+            let ln_fact = LnFactorials::up_to(total);
+            for game in games.iter().filter(|g| g.is_possible_with(bag)).take(5) {
+                println!("Game {}: posterior {:.3e} that it was played with this bag", game.id, game.bag_posterior(bag, &ln_fact));
+            }
+        },
+        None => println!("No bag of {} cubes can produce every game", total),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
+Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
+Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
+Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
+
+    fn example_games() -> Vec<Game> {
+        EXAMPLE.lines().map(|line| Game::from_line(line).unwrap().1).collect()
+    }
+
+    #[test]
+    fn test_estimate_bag() {
+        let games = example_games();
+        // the smallest feasible bag is 20 red, 13 green and 15 blue
+        assert!(estimate_bag(&games, 47).is_none());
+        assert_eq!(estimate_bag(&games, 48).unwrap().0, Set { red: 20, green: 13, blue: 15 });
+        // with room to spare the estimate lands strictly inside the feasible region
+        let (bag, _) = estimate_bag(&games, 96).unwrap();
+        assert_eq!(bag.total(), 96);
+        assert!(bag.red > 20 && bag.green > 13 && bag.blue > 15);
+        // left free, the total would run to whatever limit a search was given
+        let best_at = |total| estimate_bag(&games, total).unwrap().1;
+        assert!(best_at(48) < best_at(96) && best_at(96) < best_at(192));
+    }
+
+    #[test]
+    fn test_bag_posterior() {
+        let game = &example_games()[0];
+        let ln_fact = LnFactorials::up_to(20);
+        assert_eq!(game.bag_posterior(Set { red: 3, green: 10, blue: 7 }, &ln_fact), 0.0);
+        let total: f64 = splits(20, Set::default()).map(|bag| game.bag_posterior(bag, &ln_fact)).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+    }
 }
@@ -1,106 +1,255 @@
-use std::{time::Instant, collections::HashMap};
+use std::{time::Instant, ops::Range, fmt::Display, process::exit};
 
 use aoc2023::read_input;
 
 
-fn find_adjacent_digits(lines: &[&str], line_idx: usize, col_idx: usize) -> Vec<(usize, usize)> {
-    let mut digits = Vec::new();
-    if line_idx > 0 {
-        let above_start;
-        let above_end;
-        if col_idx > 0 {
-            above_start = col_idx - 1;
-        } else {
-            above_start = col_idx;
-        }
-        if col_idx + 1 >= lines[line_idx].len() {
-            above_end = col_idx;
-        } else {
-            above_end = col_idx + 1;
-        }
-        let above = &lines[line_idx - 1].as_bytes();
-        for col in above_start..=above_end {
-            let char_to_check = above[col];
-            if char_to_check.is_ascii_digit() {
-                digits.push((line_idx - 1, col));
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PartNumber {
+    line: usize,
+    start: usize,
+    end: usize,
+    value: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Symbol {
+    line: usize,
+    col: usize,
+    byte: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregation {
+    Product,
+    Sum,
+    Max,
+}
+
+/// Which symbols count as gears, how many distinct numbers they must touch, and how those numbers
+/// combine into the gear's ratio.
+#[derive(Debug, Clone, Copy)]
+struct GearRule<'a> {
+    symbols: &'a [u8],
+    adjacent_count: usize,
+    aggregation: Aggregation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SchematicError {
+    NumberTooLarge { line: usize, col: usize },
+    InvalidByte { line: usize, col: usize, byte: u8 },
+}
+
+impl Display for SchematicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchematicError::NumberTooLarge { line, col } =>
+                write!(f, "number at line {}, column {} does not fit in a u32", line + 1, col + 1),
+            SchematicError::InvalidByte { line, col, byte } =>
+                write!(f, "invalid byte {:#04x} at line {}, column {}", byte, line + 1, col + 1),
         }
     }
-    if line_idx + 1 < lines.len() {
-        let below_start;
-        let below_end;
-        if col_idx > 0 {
-            below_start = col_idx - 1;
-        } else {
-            below_start = col_idx;
-        }
-        if col_idx + 1 >= lines[line_idx].len() {
-            below_end = col_idx;
-        } else {
-            below_end = col_idx + 1;
+}
+
+/// An engine schematic with every number parsed once into a span and every symbol indexed.
+struct Schematic {
+    numbers: Vec<PartNumber>,
+    // indices into `numbers` for the numbers on each line, since numbers are stored in line order
+    numbers_by_line: Vec<Range<usize>>,
+    symbols: Vec<Symbol>,
+}
+
+impl PartNumber {
+    pub fn is_adjacent_to(&self, symbol: &Symbol) -> bool {
+        self.line.abs_diff(symbol.line) <= 1 && symbol.col + 1 >= self.start && symbol.col <= self.end
+    }
+}
+
+impl Aggregation {
+    pub fn apply<'a>(self, values: impl Iterator<Item = &'a PartNumber>) -> u64 {
+        let values = values.map(|n| n.value as u64);
+        match self {
+            Aggregation::Product => values.product(),
+            Aggregation::Sum => values.sum(),
+            Aggregation::Max => values.max().unwrap_or(0),
         }
-        let below = &lines[line_idx + 1].as_bytes();
-        for col in below_start..=below_end {
-            let char_to_check = below[col];
-            if char_to_check.is_ascii_digit() {
-                digits.push((line_idx + 1, col));
+    }
+}
+
+impl GearRule<'_> {
+    /// The puzzle's rule: a `*` touching exactly two numbers, multiplied together.
+    pub const STANDARD: GearRule<'static> = GearRule {
+        symbols: b"*",
+        adjacent_count: 2,
+        aggregation: Aggregation::Product,
+    };
+
+    pub fn ratio(&self, numbers: &[&PartNumber]) -> u64 {
+        self.aggregation.apply(numbers.iter().copied())
+    }
+}
+
+impl Schematic {
+    /// Lines may differ in length; adjacency only compares spans and coordinates, so nothing is
+    /// ever read from a neighbouring line.
+    pub fn from_input(input: &str) -> Result<Schematic, SchematicError> {
+        let mut numbers = Vec::new();
+        let mut numbers_by_line = Vec::new();
+        let mut symbols = Vec::new();
+        for (line_idx, line) in input.lines().enumerate() {
+            let bytes = line.as_bytes();
+            let line_numbers_start = numbers.len();
+            let mut col_idx = 0;
+            while col_idx < bytes.len() {
+                let byte = bytes[col_idx];
+                if byte.is_ascii_digit() {
+                    let num_start = col_idx;
+                    while col_idx < bytes.len() && bytes[col_idx].is_ascii_digit() {
+                        col_idx += 1;
+                    }
+                    let value = line[num_start..col_idx].parse::<u32>()
+                        .map_err(|_| SchematicError::NumberTooLarge { line: line_idx, col: num_start })?;
+                    numbers.push(PartNumber { line: line_idx, start: num_start, end: col_idx, value });
+                    continue;
+                }
+                if !byte.is_ascii_graphic() {
+                    return Err(SchematicError::InvalidByte { line: line_idx, col: col_idx, byte });
+                }
+                if byte != b'.' {
+                    symbols.push(Symbol { line: line_idx, col: col_idx, byte });
+                }
+                col_idx += 1;
             }
+            numbers_by_line.push(line_numbers_start..numbers.len());
         }
+        Ok(Schematic { numbers, numbers_by_line, symbols })
     }
-    if col_idx > 0 {
-        let char_to_check = lines[line_idx].as_bytes()[col_idx - 1];
-        if char_to_check.is_ascii_digit() {
-            digits.push((line_idx, col_idx - 1));
-        }
+
+    fn adjacent_indices<'a>(&'a self, symbol: &'a Symbol) -> impl Iterator<Item = usize> + 'a {
+        let first_line = symbol.line.saturating_sub(1);
+        let last_line = (symbol.line + 1).min(self.numbers_by_line.len() - 1);
+        let candidates = self.numbers_by_line[first_line].start..self.numbers_by_line[last_line].end;
+        candidates.filter(|&idx| self.numbers[idx].is_adjacent_to(symbol))
     }
-    if col_idx + 1 < lines[line_idx].len() {
-        let char_to_check = lines[line_idx].as_bytes()[col_idx + 1];
-        if char_to_check.is_ascii_digit() {
-            digits.push((line_idx, col_idx + 1));
+
+    pub fn numbers_adjacent_to<'a>(&'a self, symbol: &'a Symbol) -> impl Iterator<Item = &'a PartNumber> + 'a {
+        self.adjacent_indices(symbol).map(|idx| &self.numbers[idx])
+    }
+
+    /// All numbers adjacent to at least one symbol, each reported once.
+    pub fn part_numbers(&self) -> Vec<&PartNumber> {
+        let mut is_part = vec![false; self.numbers.len()];
+        for symbol in self.symbols.iter() {
+            for idx in self.adjacent_indices(symbol) {
+                is_part[idx] = true;
+            }
         }
+        self.numbers.iter().zip(is_part).filter(|(_, is_part)| *is_part).map(|(number, _)| number).collect()
     }
-    digits
-}
 
-fn num_from_digit_coords(lines: &[&str], line_idx: usize, col_idx: usize) -> (usize, u32) {
-    let line = lines[line_idx].as_bytes();
-    let mut num_start = col_idx;
-    while num_start > 0 && line[num_start - 1].is_ascii_digit() {
-        num_start -= 1;
+    /// Every symbol satisfying `rule`, with its adjacent numbers.
+    pub fn gear_candidates<'a>(&'a self, rule: &'a GearRule) -> impl Iterator<Item = (&'a Symbol, Vec<&'a PartNumber>)> + 'a {
+        self.symbols.iter()
+            .filter(|symbol| rule.symbols.contains(&symbol.byte))
+            .map(|symbol| (symbol, self.numbers_adjacent_to(symbol).collect::<Vec<_>>()))
+            .filter(|(_, numbers)| numbers.len() == rule.adjacent_count)
     }
-    let mut num_end = col_idx;
-    while num_end < line.len() && line[num_end].is_ascii_digit() {
-        num_end += 1;
+
+    pub fn gear_ratio_sum(&self, rule: &GearRule) -> u64 {
+        self.gear_candidates(rule).map(|(_, numbers)| rule.ratio(&numbers)).sum()
     }
-    let num = lines[line_idx][num_start..num_end].parse::<u32>().unwrap();
-    (num_start, num)
 }
 
 pub fn main() {
     let start_time = Instant::now();
     let input = read_input("03");
     let start_compute_time = Instant::now();
-    let mut sum = 0;
-    let lines: Vec<&str> = input.lines().collect();
-    for line_idx in 0..lines.len() {
-        let line = lines[line_idx];
-        for (col_idx, c) in line.as_bytes().iter().enumerate() {
-            if *c == b'*' {
-                let adjacents = find_adjacent_digits(&lines, line_idx, col_idx);
-                if adjacents.len() > 1 {
-                    let mut num_coords_to_nums: HashMap<(usize, usize), u32> = HashMap::new();
-                    for (digit_line, digit_col) in adjacents.iter() {
-                        let (num_start, num) = num_from_digit_coords(&lines, *digit_line, *digit_col);
-                        num_coords_to_nums.insert((*digit_line, num_start), num);
-                    }
-                    if num_coords_to_nums.len() == 2 {
-                        sum += num_coords_to_nums.values().product::<u32>();
-                    }
-                }
-
-            }
-        }
-    }
+    let schematic = match Schematic::from_input(&input) {
+        Ok(schematic) => schematic,
+        Err(e) => {
+            eprintln!("Malformed schematic: {}", e);
+            exit(1);
+        },
+    };
+    let part_sum: u32 = schematic.part_numbers().iter().map(|n| n.value).sum();
+    let sum = schematic.gear_ratio_sum(&GearRule::STANDARD);
     println!("Total time: {:?}, compute time: {:?}", start_time.elapsed(), start_compute_time.elapsed());
+    println!("{}", part_sum);
     println!("{}", sum);
+
+    // variants of the gear rule answered by the same scan
+    let mut all_symbols: Vec<u8> = schematic.symbols.iter().map(|s| s.byte).collect();
+    all_symbols.sort_unstable();
+    all_symbols.dedup();
+    let variants = [
+        GearRule { symbols: &all_symbols, adjacent_count: 2, aggregation: Aggregation::Sum },
+        GearRule { symbols: b"*", adjacent_count: 3, aggregation: Aggregation::Max },
+    ];
+    for rule in variants.iter() {
+        println!("{:?} of {} numbers around any of {:?}: {}", rule.aggregation, rule.adjacent_count,
+            String::from_utf8_lossy(rule.symbols), schematic.gear_ratio_sum(rule));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "467..114..
+...*......
+..35..633.
+......#...
+617*......
+.....+.58.
+..592.....
+......755.
+...$.*....
+.664.598..";
+
+    #[test]
+    fn test_example() {
+        let schematic = Schematic::from_input(EXAMPLE).unwrap();
+        assert_eq!(schematic.part_numbers().iter().map(|n| n.value).sum::<u32>(), 4361);
+        assert_eq!(schematic.gear_ratio_sum(&GearRule::STANDARD), 467835);
+        let first_gear = &schematic.symbols[0];
+        let adjacent: Vec<u32> = schematic.numbers_adjacent_to(first_gear).map(|n| n.value).collect();
+        assert_eq!(adjacent, vec![467, 35]);
+    }
+
+    #[test]
+    fn test_gear_rules() {
+        // the `*` touches 2, 3 and 4; the `#` touches 5 and 6
+        let schematic = Schematic::from_input("2.3..5\n.*..#.\n4....6\n").unwrap();
+        let rule = |symbols, adjacent_count, aggregation| GearRule { symbols, adjacent_count, aggregation };
+        assert_eq!(schematic.gear_ratio_sum(&GearRule::STANDARD), 0);
+        assert_eq!(schematic.gear_ratio_sum(&rule(b"*", 3, Aggregation::Product)), 24);
+        assert_eq!(schematic.gear_ratio_sum(&rule(b"*", 3, Aggregation::Sum)), 9);
+        assert_eq!(schematic.gear_ratio_sum(&rule(b"*", 3, Aggregation::Max)), 4);
+        assert_eq!(schematic.gear_ratio_sum(&rule(b"#", 2, Aggregation::Product)), 30);
+        assert_eq!(schematic.gear_ratio_sum(&rule(b"*#", 2, Aggregation::Sum)), 11);
+        assert_eq!(schematic.gear_ratio_sum(&rule(b"*#", 3, Aggregation::Sum)), 9);
+        assert_eq!(schematic.gear_candidates(&rule(b"#*", 2, Aggregation::Max)).count(), 1);
+    }
+
+    #[test]
+    fn test_ragged_lines() {
+        // the `*` at the end of the short middle line touches numbers diagonally on both longer lines
+        let schematic = Schematic::from_input("......12\n......*\n.......345\n").unwrap();
+        assert_eq!(schematic.gear_ratio_sum(&GearRule::STANDARD), 12 * 345);
+        // a number past the end of a shorter neighbouring line is not adjacent
+        let schematic = Schematic::from_input("..*\n.....7\n").unwrap();
+        assert!(schematic.part_numbers().is_empty());
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(
+            Schematic::from_input("...\n.1 2\n").err(),
+            Some(SchematicError::InvalidByte { line: 1, col: 2, byte: b' ' }),
+        );
+        assert_eq!(
+            Schematic::from_input("*.99999999999\n").err(),
+            Some(SchematicError::NumberTooLarge { line: 0, col: 2 }),
+        );
+    }
 }
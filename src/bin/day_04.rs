@@ -1,16 +1,49 @@
-use std::{collections::HashSet, time::Instant};
+use std::{collections::VecDeque, time::Instant};
 
 use nom::{
     IResult,
-    character::complete::{u32 as parse_u32, multispace1, multispace0}, sequence::tuple, bytes::complete::tag, multi::separated_list1,
+    character::complete::{u32 as parse_u32, multispace1, multispace0}, sequence::tuple, bytes::complete::tag, multi::fold_many1,
+    combinator::verify,
 };
 
 use aoc2023::read_input;
 
+/// Set of numbers below 128 packed into the bits of a `u128`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+struct NumberSet(u128);
+
+impl NumberSet {
+    pub const MAX: u32 = 127;
+
+    pub fn insert(&mut self, number: u32) {
+        self.0 |= 1 << number;
+    }
+
+    pub fn intersection(self, other: NumberSet) -> NumberSet {
+        NumberSet(self.0 & other.0)
+    }
+
+    pub fn len(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Parses whitespace-separated numbers directly into a set, without an intermediate `Vec`.
+    fn parse(input: &str) -> IResult<&str, NumberSet> {
+        fold_many1(
+            tuple((multispace0, verify(parse_u32, |&n| n <= NumberSet::MAX))),
+            NumberSet::default,
+            |mut set, (_, number)| {
+                set.insert(number);
+                set
+            },
+        )(input)
+    }
+}
+
 struct Card {
     id: u32,
-    winning_numbers: HashSet<u32>,
-    my_numbers: HashSet<u32>,
+    winning_numbers: NumberSet,
+    my_numbers: NumberSet,
 }
 
 impl Card {
@@ -21,22 +54,96 @@ impl Card {
             parse_u32,
             tag(":"),
             multispace0,
-            separated_list1(multispace1, parse_u32),
+            NumberSet::parse,
             multispace0,
             tag("|"),
             multispace0,
-            separated_list1(multispace1, parse_u32),
+            NumberSet::parse,
         ))(line)?;
         Ok((i, Card {
             id,
-            winning_numbers: winning_numbers.into_iter().collect(),
-            my_numbers: my_numbers.into_iter().collect(),
+            winning_numbers,
+            my_numbers,
         }))
     }
 
     pub fn count_winning(&self) -> u32 {
-        self.winning_numbers.intersection(&self.my_numbers).count() as u32
+        self.winning_numbers.intersection(self.my_numbers).len()
+    }
+
+    /// Part 1 score: one point for the first match, doubled for each match after that. A card can
+    /// match all 128 possible numbers, so this needs the full width of a `u128`.
+    pub fn points(&self) -> u128 {
+        match self.count_winning() {
+            0 => 0,
+            count => 1 << (count - 1),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CardTrace {
+    id: u32,
+    matches: u32,
+    points: u128,
+    copies: u64,
+    // ids of the cards whose matches won copies of this one
+    spawned_by: Vec<u32>,
+}
+
+/// Plays out the copy-winning rules and reports, for each card in order, how many copies are held
+/// and where they came from. Card ids must run sequentially from 1. Copy counts can grow
+/// exponentially along the table, so a count that overflows a `u64` is reported as an error.
+fn simulate_copies(cards: &[Card]) -> Result<Vec<CardTrace>, String> {
+    let mut traces = Vec::with_capacity(cards.len());
+    for (card, expected_id) in cards.iter().zip(1..) {
+        if card.id != expected_id {
+            return Err(format!("Expected card {} but found card {}", expected_id, card.id));
+        }
+        traces.push(CardTrace {
+            id: card.id,
+            matches: card.count_winning(),
+            points: card.points(),
+            copies: 1,
+            spawned_by: Vec::new(),
+        });
+    }
+    for i in 0..traces.len() {
+        let (id, copies) = (traces[i].id, traces[i].copies);
+        // wins past the end of the table are dropped
+        let last_won = (i + traces[i].matches as usize).min(traces.len() - 1);
+        for won in traces[i + 1..=last_won].iter_mut() {
+            won.copies = won.copies.checked_add(copies)
+                .ok_or_else(|| format!("Copies of card {} overflow a u64", won.id))?;
+            won.spawned_by.push(id);
+        }
+    }
+    Ok(traces)
+}
+
+/// Scores cards one line at a time, returning the total points and total cards held. Only the
+/// copies already won for upcoming cards are kept in memory, so the stream can be arbitrarily long.
+/// The card count grows exponentially with the number of matches, so totals that overflow are
+/// reported as errors.
+fn score_stream<'a>(lines: impl Iterator<Item = &'a str>) -> Result<(u128, u64), String> {
+    let mut points = 0u128;
+    let mut total_cards = 0u64;
+    let mut pending_copies: VecDeque<u64> = VecDeque::new();
+    for line in lines {
+        let (_, card) = Card::from_line(line).map_err(|e| format!("Could not parse {:?}: {}", line, e))?;
+        let overflow = || format!("Totals overflow at card {}", card.id);
+        points = points.checked_add(card.points()).ok_or_else(overflow)?;
+        let copies = pending_copies.pop_front().unwrap_or(0).checked_add(1).ok_or_else(overflow)?;
+        total_cards = total_cards.checked_add(copies).ok_or_else(overflow)?;
+        let matches = card.count_winning() as usize;
+        if pending_copies.len() < matches {
+            pending_copies.resize(matches, 0);
+        }
+        for pending in pending_copies.iter_mut().take(matches) {
+            *pending = pending.checked_add(copies).ok_or_else(overflow)?;
+        }
     }
+    Ok((points, total_cards))
 }
 
 pub fn main() {
@@ -50,20 +157,85 @@ pub fn main() {
         let (_, card) = Card::from_line(line).unwrap();
         cards.push(card);
     }
-    // compute winning values in reverse order
-    let mut card_values = vec![0u32; cards.len()];
-    for i in (0..cards.len()).rev() {
-        let card = &cards[i];
-        let win_count = card.count_winning();
-        if win_count == 0 {
-            card_values[i] = 1;
-        } else {
-            let transitive_card_count: u32 = card_values[i + 1..i + 1 + win_count as usize].iter().copied().sum();
-            card_values[i] = transitive_card_count + 1;
-        }
-    }
+    let traces = simulate_copies(&cards).unwrap();
 
-    let sum: u32 = card_values.iter().sum();
+    let (points, sum) = score_stream(input.lines()).unwrap();
     println!("Total time: {:?}", start_time.elapsed());
+    for trace in traces.iter().take(10) {
+        println!("Card {}: {} matches, {} points, {} copies (won from cards {:?})",
+            trace.id, trace.matches, trace.points, trace.copies, trace.spawned_by);
+    }
+    println!("{}", points);
     println!("{}", sum);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
+Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
+Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
+Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
+Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
+
+    fn parse_cards(input: &str) -> Vec<Card> {
+        input.lines().map(|line| Card::from_line(line).unwrap().1).collect()
+    }
+
+    #[test]
+    fn test_number_set() {
+        let (rest, set) = NumberSet::parse("5 127 5 64").unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(set.len(), 3);
+        let (_, other) = NumberSet::parse("64 1 127").unwrap();
+        assert_eq!(set.intersection(other).len(), 2);
+        // numbers above `NumberSet::MAX` end the set instead of being stored
+        let (rest, set) = NumberSet::parse("1 128").unwrap();
+        assert_eq!(rest, " 128");
+        assert_eq!(set.len(), 1);
+        assert!(Card::from_line("Card 1: 1 128 | 1 2").is_err());
+    }
+
+    #[test]
+    fn test_simulate_copies() {
+        let traces = simulate_copies(&parse_cards(EXAMPLE)).unwrap();
+        assert_eq!(traces.iter().map(|t| t.points).sum::<u128>(), 13);
+        assert_eq!(traces.iter().map(|t| t.copies).sum::<u64>(), 30);
+        assert_eq!(traces[3].copies, 8);
+        assert_eq!(traces[3].spawned_by, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_simulate_copies_non_sequential() {
+        let cards = parse_cards("Card 1: 1 2 | 1 3\nCard 3: 4 5 | 6 7");
+        assert_eq!(simulate_copies(&cards).unwrap_err(), "Expected card 2 but found card 3");
+    }
+
+    #[test]
+    fn test_simulate_copies_overflow() {
+        let input: Vec<String> = (1..=100).map(|id| format!("Card {}: 1 2 3 | 1 2 3", id)).collect();
+        let cards = parse_cards(&input.join("\n"));
+        let error = simulate_copies(&cards).unwrap_err();
+        assert!(error.ends_with("overflow a u64"), "{}", error);
+        assert!(simulate_copies(&cards[..60]).is_ok());
+    }
+
+    #[test]
+    fn test_score_stream() {
+        assert_eq!(score_stream(EXAMPLE.lines()), Ok((13, 30)));
+        let input: Vec<String> = (1..=100).map(|id| format!("Card {}: 1 2 3 | 1 2 3", id)).collect();
+        let error = score_stream(input.iter().map(String::as_str)).unwrap_err();
+        assert!(error.starts_with("Totals overflow at card"), "{}", error);
+        assert!(score_stream(input[..60].iter().map(String::as_str)).is_ok());
+    }
+
+    #[test]
+    fn test_points_many_matches() {
+        let numbers: Vec<String> = (1..=34).map(|n| n.to_string()).collect();
+        let line = format!("Card 1: {} | {}", numbers.join(" "), numbers.join(" "));
+        let (_, card) = Card::from_line(&line).unwrap();
+        assert_eq!(card.points(), 1 << 33);
+    }
+}
@@ -1,104 +1,332 @@
-use std::{cmp::Ordering, time::Instant, mem::swap};
+use std::time::Instant;
 
 use aoc2023::read_input;
 use nom::{
     IResult,
     character::complete::{
-        u64 as parse_u64, multispace1, not_line_ending, newline
+        u64 as parse_u64, multispace1, alpha1, newline
     },
-    sequence::{tuple, terminated, separated_pair}, bytes::complete::tag, multi::separated_list1,
+    sequence::{tuple, terminated}, bytes::complete::tag, multi::separated_list1, combinator::verify,
 };
 
+/// Whether the seeds line lists individual seeds (part 1) or `start length` pairs (part 2).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SeedReading {
+    Individual,
+    Ranges,
+}
+
 #[derive(Clone, Default, Debug)]
 struct Seeds {
-    ranges: Vec<(u64, u64)>,
+    ranges: IntervalSet,
 }
 
 struct Mapping {
     dst_start: u64,
     src_start: u64,
-    src_end: u64,
+    src_end: u128,
+}
+
+/// One `<source>-to-<destination> map` section of the almanac.
+struct Stage<'a> {
+    source: &'a str,
+    destination: &'a str,
+    map: RangeMap,
+}
+
+/// Stages chained in order from `seed` to `location`.
+struct Almanac<'a> {
+    stages: Vec<Stage<'a>>,
+}
+
+/// Set of u64 values stored as sorted, disjoint, non-adjacent half-open intervals. Bounds are
+/// `u128` so that an interval can end past `u64::MAX` and include it.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+struct IntervalSet {
+    intervals: Vec<(u128, u128)>,
+}
+
+/// Maps every value in `start..end` to `value + offset`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Segment {
+    start: u128,
+    end: u128,
+    offset: i128,
+}
+
+/// Piecewise offset function over every u64 value, `0..DOMAIN_END`. Segments are sorted and contiguous, gaps between
+/// the almanac's mappings are explicit identity segments, and neighbours never share an offset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct RangeMap {
+    segments: Vec<Segment>,
+}
+
+/// One past the largest u64, the end of the domain every `RangeMap` covers.
+const DOMAIN_END: u128 = 1 << 64;
+
+/// Offsets a bound. Maps only hold segments whose images stay inside `0..=DOMAIN_END`, so this
+/// never leaves the domain.
+fn shift(value: u128, offset: i128) -> u128 {
+    (value as i128 + offset) as u128
 }
 
 impl Seeds {
-    pub fn from_input(input: &str) -> IResult<&str, Seeds> {
-        let (i, (_, ranges, _)) = tuple((
+    pub fn from_input(input: &str, reading: SeedReading) -> IResult<&str, Seeds> {
+        let (i, (_, numbers, _)) = tuple((
             tag("seeds: "),
-            separated_list1(tag(" "), separated_pair(parse_u64, tag(" "), parse_u64)),
+            verify(
+                separated_list1(tag(" "), parse_u64),
+                |numbers: &Vec<u64>| match reading {
+                    SeedReading::Individual => true,
+                    SeedReading::Ranges => numbers.len().is_multiple_of(2)
+                        && numbers.chunks(2).all(|pair| pair[0] as u128 + pair[1] as u128 <= DOMAIN_END),
+                },
+            ),
             multispace1,
         ))(input)?;
+        let intervals = match reading {
+            SeedReading::Individual => numbers.into_iter().map(|s| (s as u128, s as u128 + 1)).collect(),
+            SeedReading::Ranges => numbers.chunks(2).map(|pair| (pair[0] as u128, pair[0] as u128 + pair[1] as u128)).collect(),
+        };
         Ok((i, Seeds {
-            ranges: ranges.into_iter().map(|(s, l)| (s, s + l)).collect()
+            ranges: IntervalSet::from_intervals(intervals)
         }))
     }
 
-    pub fn map_ranges(&self, mappings: &[Mapping]) -> Seeds {
-        let mut unmapped_ranges = self.ranges.clone();
-        let mut remapped_ranges: Vec<(u64, u64)> = Vec::new();
-        for mapping in mappings.iter() {
-            let mut i = 0;
-            while i < unmapped_ranges.len() {
-                let seed_range = unmapped_ranges[i];
-                let mut remapped_range;
-                match (
-                    mapping.src_start.cmp(&seed_range.0),
-                    mapping.src_end.cmp(&seed_range.0),
-                    mapping.src_start.cmp(&seed_range.1),
-                    mapping.src_end.cmp(&seed_range.1),
-                ) {
-                    (_, Ordering::Less | Ordering::Equal, _, _) |
-                    (_, _, Ordering::Greater | Ordering::Equal, _) => {
-                        // mapping is strictly smaller than seed range or strictly larger so do nothing
-                        i += 1;
-                        continue;
-                    },
-                    (Ordering::Less | Ordering::Equal, Ordering::Greater, _, Ordering::Less) => {
-                        // mapping partially left overlaps range
-                        remapped_range = (seed_range.0, mapping.src_end);
-                        let nonmapped_range = (mapping.src_end, seed_range.1);
-                        unmapped_ranges[i] = nonmapped_range;
-                        i += 1;
-                    },
-                    (Ordering::Greater, _, Ordering::Less, Ordering::Greater | Ordering::Equal) => {
-                        // mapping partially right overlaps range
-                        remapped_range = (mapping.src_start, seed_range.1);
-                        let nonmapped_range = (seed_range.0, mapping.src_start);
-                        unmapped_ranges[i] = nonmapped_range;
-                        i += 1;
-                    },
-                    (Ordering::Greater, _, Ordering::Less, Ordering::Less) => {
-                        // mapping is internal to seed range!
-                        remapped_range = (mapping.src_start, mapping.src_end);
-                        let nonmapped_range1 = (seed_range.0, mapping.src_start);
-                        let nonmapped_range2 = (mapping.src_end, seed_range.1);
-                        unmapped_ranges[i] = nonmapped_range1;
-                        unmapped_ranges.push(nonmapped_range2);
-                        i += 1;
-                    },
-                    (Ordering::Less | Ordering::Equal, _, _, Ordering::Greater | Ordering::Equal) => {
-                        // seed range is entirely remapped
-                        remapped_range = (seed_range.0, seed_range.1);
-                        // remove from unmapped and don't increment loop
-                        unmapped_ranges.remove(i);
-                    },
-                }
-                let mapping_delta = mapping.dst_start as i64 - mapping.src_start as i64;
-                remapped_range.0 = remapped_range.0.saturating_add_signed(mapping_delta);
-                remapped_range.1 = remapped_range.1.saturating_add_signed(mapping_delta);
-                remapped_ranges.push(remapped_range);
+    /// The lowest location reached by any seed, along with the lowest seed that reaches it.
+    pub fn lowest_location(&self, seed_to_location: &RangeMap) -> Option<(u64, u64)> {
+        let location = seed_to_location.apply_intervals(&self.ranges).min()?;
+        let seed = seed_to_location.preimage(location).intersection(&self.ranges).min()?;
+        Some((seed, location))
+    }
+}
+
+impl IntervalSet {
+    pub fn from_intervals(mut intervals: Vec<(u128, u128)>) -> IntervalSet {
+        intervals.retain(|&(start, end)| start < end);
+        intervals.sort_unstable();
+        let mut merged: Vec<(u128, u128)> = Vec::with_capacity(intervals.len());
+        for (start, end) in intervals {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        IntervalSet { intervals: merged }
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        self.intervals.first().map(|&(start, _)| start as u64)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intersected = Vec::new();
+        for &(start, end) in self.intervals.iter() {
+            let first = other.intervals.partition_point(|&(_, other_end)| other_end <= start);
+            for &(other_start, other_end) in other.intervals[first..].iter().take_while(|&&(s, _)| s < end) {
+                intersected.push((start.max(other_start), end.min(other_end)));
+            }
+        }
+        IntervalSet::from_intervals(intersected)
+    }
+}
+
+impl RangeMap {
+    pub fn identity() -> RangeMap {
+        RangeMap { segments: vec![Segment { start: 0, end: DOMAIN_END, offset: 0 }] }
+    }
+
+    /// Builds a map from the almanac's mappings, rejecting any whose source or destination range
+    /// runs past `u64::MAX`.
+    pub fn from_mappings(mappings: &[Mapping]) -> Result<RangeMap, String> {
+        for m in mappings.iter() {
+            let dst_end = m.dst_start as u128 + (m.src_end - m.src_start as u128);
+            if m.src_end > DOMAIN_END || dst_end > DOMAIN_END {
+                return Err(format!("Mapping {}..{} to {}..{} does not fit in a u64", m.src_start, m.src_end, m.dst_start, dst_end));
+            }
+        }
+        let mut segments: Vec<Segment> = mappings.iter()
+            .map(|m| Segment { start: m.src_start as u128, end: m.src_end, offset: m.dst_start as i128 - m.src_start as i128 })
+            .collect();
+        segments.sort_unstable_by_key(|s| s.start);
+        // clip overlaps so each value is covered at most once
+        let mut cursor = 0;
+        for segment in segments.iter_mut() {
+            segment.start = segment.start.max(cursor);
+            cursor = cursor.max(segment.end);
+        }
+        segments.retain(|s| s.start < s.end);
+        Ok(RangeMap::normalized(segments))
+    }
+
+    /// Builds a map from sorted, non-overlapping segments, filling gaps with identity segments and
+    /// merging neighbours that share an offset.
+    fn normalized(segments: Vec<Segment>) -> RangeMap {
+        let mut normal: Vec<Segment> = Vec::with_capacity(segments.len() * 2 + 1);
+        let mut push = |segment: Segment| {
+            match normal.last_mut() {
+                Some(last) if last.offset == segment.offset => last.end = segment.end,
+                _ => normal.push(segment),
+            }
+        };
+        let mut cursor = 0;
+        for segment in segments {
+            if segment.start > cursor {
+                push(Segment { start: cursor, end: segment.start, offset: 0 });
+            }
+            cursor = segment.end;
+            push(segment);
+        }
+        if cursor < DOMAIN_END {
+            push(Segment { start: cursor, end: DOMAIN_END, offset: 0 });
+        }
+        RangeMap { segments: normal }
+    }
+
+    fn segment_index(&self, value: u128) -> usize {
+        self.segments.partition_point(|s| s.end <= value)
+    }
+
+    pub fn apply(&self, value: u64) -> u64 {
+        let value = value as u128;
+        shift(value, self.segments[self.segment_index(value)].offset) as u64
+    }
+
+    pub fn apply_intervals(&self, set: &IntervalSet) -> IntervalSet {
+        let mut mapped = Vec::new();
+        for &(start, end) in set.intervals.iter() {
+            let mut idx = self.segment_index(start);
+            let mut cursor = start;
+            while cursor < end {
+                let segment = self.segments[idx];
+                let piece_end = segment.end.min(end);
+                mapped.push((shift(cursor, segment.offset), shift(piece_end, segment.offset)));
+                cursor = piece_end;
+                idx += 1;
+            }
+        }
+        IntervalSet::from_intervals(mapped)
+    }
+
+    /// Composes two maps into one that applies `self` and then `next`.
+    pub fn then(&self, next: &RangeMap) -> RangeMap {
+        let mut segments = Vec::new();
+        for segment in self.segments.iter() {
+            let image_start = shift(segment.start, segment.offset);
+            let image_end = shift(segment.end, segment.offset);
+            let mut idx = next.segment_index(image_start);
+            let mut cursor = image_start;
+            while cursor < image_end {
+                let next_segment = next.segments[idx];
+                let piece_end = next_segment.end.min(image_end);
+                segments.push(Segment {
+                    start: shift(cursor, -segment.offset),
+                    end: shift(piece_end, -segment.offset),
+                    offset: segment.offset + next_segment.offset,
+                });
+                cursor = piece_end;
+                idx += 1;
+            }
+        }
+        RangeMap::normalized(segments)
+    }
+
+    /// All values that map into `set`. Works even when the map is not one-to-one.
+    pub fn preimage_intervals(&self, set: &IntervalSet) -> IntervalSet {
+        let mut preimage = Vec::new();
+        for segment in self.segments.iter() {
+            let image = IntervalSet {
+                intervals: vec![(shift(segment.start, segment.offset), shift(segment.end, segment.offset))],
+            };
+            for &(start, end) in image.intersection(set).intervals.iter() {
+                preimage.push((shift(start, -segment.offset), shift(end, -segment.offset)));
+            }
+        }
+        IntervalSet::from_intervals(preimage)
+    }
+
+    pub fn preimage(&self, value: u64) -> IntervalSet {
+        self.preimage_intervals(&IntervalSet { intervals: vec![(value as u128, value as u128 + 1)] })
+    }
+
+    /// The inverse function, if this map is a bijection of the u64 values.
+    pub fn inverse(&self) -> Option<RangeMap> {
+        let mut segments: Vec<Segment> = self.segments.iter()
+            .map(|s| Segment { start: shift(s.start, s.offset), end: shift(s.end, s.offset), offset: -s.offset })
+            .collect();
+        segments.sort_unstable_by_key(|s| s.start);
+        let mut cursor = 0;
+        for segment in segments.iter() {
+            if segment.start != cursor {
+                return None;
             }
+            cursor = segment.end;
+        }
+        if cursor != DOMAIN_END {
+            return None;
         }
-        // any ranges that weren't mapped are passed on to the next stage unmodified
-        remapped_ranges.append(&mut unmapped_ranges);
-        let next_seeds = Seeds { ranges: remapped_ranges };
-        next_seeds
+        Some(RangeMap::normalized(segments))
+    }
+}
+
+impl<'a> Stage<'a> {
+    /// Builds a stage, rejecting mappings whose source ranges overlap or run past `u64::MAX`.
+    pub fn new(source: &'a str, destination: &'a str, mut mappings: Vec<Mapping>) -> Result<Stage<'a>, String> {
+        mappings.sort_unstable_by_key(|m| m.src_start);
+        for pair in mappings.windows(2) {
+            if (pair[1].src_start as u128) < pair[0].src_end {
+                return Err(format!("{}-to-{} map has overlapping source ranges {}..{} and {}..{}",
+                    source, destination, pair[0].src_start, pair[0].src_end, pair[1].src_start, pair[1].src_end));
+            }
+        }
+        Ok(Stage { source, destination, map: RangeMap::from_mappings(&mappings)? })
+    }
+}
+
+impl<'a> Almanac<'a> {
+    /// Checks that the stages form one connected chain from `seed` to `location`.
+    pub fn from_stages(stages: Vec<Stage<'a>>) -> Result<Almanac<'a>, String> {
+        let mut category = "seed";
+        for stage in stages.iter() {
+            if stage.source != category {
+                return Err(format!("Expected a map from {} but found {}-to-{}", category, stage.source, stage.destination));
+            }
+            category = stage.destination;
+        }
+        if category != "location" {
+            return Err(format!("Almanac ends at {} instead of location", category));
+        }
+        Ok(Almanac { stages })
+    }
+
+    /// Collapses the stages up to and including `category` into a single function from seeds.
+    pub fn map_to(&self, category: &str) -> Option<RangeMap> {
+        if category == "seed" {
+            return Some(RangeMap::identity());
+        }
+        let end = self.stages.iter().position(|s| s.destination == category)?;
+        Some(self.stages[..=end].iter().fold(RangeMap::identity(), |acc, stage| acc.then(&stage.map)))
+    }
+
+    pub fn category_value(&self, seed: u64, category: &str) -> Option<u64> {
+        let end = match category {
+            "seed" => 0,
+            _ => self.stages.iter().position(|s| s.destination == category)? + 1,
+        };
+        Some(self.stages[..end].iter().fold(seed, |value, stage| stage.map.apply(value)))
     }
 }
 
-fn mappings_from_input(input: &str) -> IResult<&str, Vec<Mapping>> {
-    let (i, (_, _, tuples, _)) = tuple((
-        not_line_ending,
-        newline,
+fn stage_from_input(input: &str) -> IResult<&str, (&str, &str, Vec<Mapping>)> {
+    let (i, (source, _, destination, _, tuples, _)) = tuple((
+        alpha1,
+        tag("-to-"),
+        alpha1,
+        tag(" map:\n"),
         separated_list1(newline,
             tuple((
                 terminated(parse_u64, multispace1),
@@ -108,19 +336,16 @@ fn mappings_from_input(input: &str) -> IResult<&str, Vec<Mapping>> {
         multispace1,
     ))(input)?;
     let mappings: Vec<_> = tuples.into_iter()
-        .map(|(dst_start, src_start, length)| Mapping { dst_start, src_start, src_end: src_start + length })
+        .map(|(dst_start, src_start, length)| Mapping { dst_start, src_start, src_end: src_start as u128 + length as u128 })
         .collect();
-    Ok((i, mappings))
+    Ok((i, (source, destination, mappings)))
 }
 
-pub fn main() {
-    let start_time = Instant::now();
-    let input = read_input("05");
-    let (mut remaining_input, mut seeds) = Seeds::from_input(&input).unwrap();
-
-    let mut next_seeds;
+/// Parses every map section following the seeds line into a validated almanac.
+fn almanac_from_input(mut remaining_input: &str) -> Almanac<'_> {
+    let mut stages = Vec::new();
     loop {
-        match mappings_from_input(remaining_input) {
+        match stage_from_input(remaining_input) {
             Err(e) => {
                 match e {
                     nom::Err::Error(f) => {
@@ -132,13 +357,209 @@ pub fn main() {
                 }
                 break;
             },
-            Ok((i, mappings)) => {
+            Ok((i, (source, destination, mappings))) => {
                 remaining_input = i;
-                next_seeds = seeds.map_ranges(&mappings);
+                stages.push(Stage::new(source, destination, mappings).unwrap());
             }
         }
-        swap(&mut seeds, &mut next_seeds);
     }
+    Almanac::from_stages(stages).unwrap()
+}
+
+pub fn main() {
+    let start_time = Instant::now();
+    let input = read_input("05");
+    let (remaining_input, seeds) = Seeds::from_input(&input, SeedReading::Ranges).unwrap();
+    let (_, individual_seeds) = Seeds::from_input(&input, SeedReading::Individual).unwrap();
+
+    let almanac = almanac_from_input(remaining_input);
+    // collapse the whole almanac into a single seed-to-location function
+    let seed_to_location = almanac.map_to("location").unwrap();
+    for seed in seeds.ranges.intervals.iter().map(|&(start, _)| start as u64) {
+        debug_assert_eq!(seed_to_location.apply(seed), almanac.category_value(seed, "location").unwrap());
+    }
+    let (individual_seed, individual_min_location) = individual_seeds.lowest_location(&seed_to_location).unwrap();
+    let (seed, min_location) = seeds.lowest_location(&seed_to_location).unwrap();
     println!("Total time: {:?}", start_time.elapsed());
-    println!("{}", seeds.ranges.iter().map(|t| t.0).min().unwrap());
+    println!("{} (from seed {})", individual_min_location, individual_seed);
+    println!("{} (from seed {})", min_location, seed);
+
+    // verify the answer by running the almanac backwards
+    let below_min = IntervalSet { intervals: vec![(0, min_location as u128)] };
+    let seeds_below_min = seed_to_location.preimage_intervals(&below_min).intersection(&seeds.ranges);
+    if !seeds_below_min.is_empty() {
+        panic!("Reverse lookup disagrees: {:?} map below {}", seeds_below_min, min_location);
+    }
+    let inverse_stages: Option<Vec<RangeMap>> = almanac.stages.iter().rev().map(|s| s.map.inverse()).collect();
+    match inverse_stages {
+        Some(inverse_stages) => {
+            let inverse = inverse_stages.iter().fold(RangeMap::identity(), |acc, stage| acc.then(stage));
+            assert_eq!(Some(&inverse), seed_to_location.inverse().as_ref());
+            assert_eq!(inverse.apply(min_location), seed);
+            for stage in almanac.stages.iter() {
+                println!("  {} {}", stage.destination, almanac.category_value(seed, stage.destination).unwrap());
+            }
+        },
+        None => println!("Almanac is not invertible"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4
+";
+
+    fn mapping(dst_start: u64, src_start: u64, length: u64) -> Mapping {
+        Mapping { dst_start, src_start, src_end: src_start as u128 + length as u128 }
+    }
+
+    #[test]
+    fn test_lowest_location() {
+        let (rest, seeds) = Seeds::from_input(EXAMPLE, SeedReading::Ranges).unwrap();
+        let (_, individual_seeds) = Seeds::from_input(EXAMPLE, SeedReading::Individual).unwrap();
+        let seed_to_location = almanac_from_input(rest).map_to("location").unwrap();
+        assert_eq!(individual_seeds.lowest_location(&seed_to_location), Some((13, 35)));
+        assert_eq!(seeds.lowest_location(&seed_to_location), Some((82, 46)));
+    }
+
+    #[test]
+    fn test_seed_readings() {
+        let (_, seeds) = Seeds::from_input("seeds: 79 14 55 13\n", SeedReading::Ranges).unwrap();
+        assert_eq!(seeds.ranges, IntervalSet { intervals: vec![(55, 68), (79, 93)] });
+        let (_, seeds) = Seeds::from_input("seeds: 79 14 55 13\n", SeedReading::Individual).unwrap();
+        assert_eq!(seeds.ranges, IntervalSet { intervals: vec![(13, 15), (55, 56), (79, 80)] });
+        let (_, seeds) = Seeds::from_input("seeds: 79 14 55\n", SeedReading::Individual).unwrap();
+        assert_eq!(seeds.ranges.min(), Some(14));
+        // ranges need a length for every start
+        assert!(Seeds::from_input("seeds: 79 14 55\n", SeedReading::Ranges).is_err());
+    }
+
+    #[test]
+    fn test_composition() {
+        let (rest, _) = Seeds::from_input(EXAMPLE, SeedReading::Ranges).unwrap();
+        let almanac = almanac_from_input(rest);
+        for stage in almanac.stages.iter() {
+            let collapsed = almanac.map_to(stage.destination).unwrap();
+            for seed in 0..120 {
+                assert_eq!(Some(collapsed.apply(seed)), almanac.category_value(seed, stage.destination));
+            }
+        }
+    }
+
+    #[test]
+    fn test_preimage_and_inverse() {
+        let (rest, _) = Seeds::from_input(EXAMPLE, SeedReading::Ranges).unwrap();
+        let almanac = almanac_from_input(rest);
+        let seed_to_location = almanac.map_to("location").unwrap();
+        let seeds = IntervalSet::from_intervals(vec![(79, 93), (55, 68)]);
+        assert_eq!(seed_to_location.preimage(46).intersection(&seeds), IntervalSet { intervals: vec![(82, 83)] });
+        // no seed in the ranges reaches a location below the answer
+        let below = IntervalSet { intervals: vec![(0, 46)] };
+        assert!(seed_to_location.preimage_intervals(&below).intersection(&seeds).is_empty());
+
+        let inverse = seed_to_location.inverse().unwrap();
+        assert_eq!(inverse.apply(46), 82);
+        let inverse_stages = almanac.stages.iter().rev()
+            .map(|stage| stage.map.inverse().unwrap())
+            .fold(RangeMap::identity(), |acc, stage| acc.then(&stage));
+        assert_eq!(inverse_stages, inverse);
+        for seed in 0..120 {
+            assert_eq!(inverse.apply(seed_to_location.apply(seed)), seed);
+        }
+
+        // 0..10 lands on 20..30, which also maps to itself
+        let merging = RangeMap::from_mappings(&[mapping(20, 0, 10)]).unwrap();
+        assert_eq!(merging.preimage(25), IntervalSet { intervals: vec![(5, 6), (25, 26)] });
+        assert_eq!(merging.inverse(), None);
+    }
+
+    #[test]
+    fn test_offsets_beyond_i64() {
+        let map = RangeMap::from_mappings(&[mapping(0, 1 << 63, 10), mapping(u64::MAX - 20, 0, 10)]).unwrap();
+        assert_eq!(map.apply((1 << 63) + 3), 3);
+        assert_eq!(map.apply(3), u64::MAX - 17);
+        assert_eq!(map.apply(10), 10);
+    }
+
+    #[test]
+    fn test_full_domain() {
+        let top = RangeMap::from_mappings(&[mapping(u64::MAX - 9, 0, 10)]).unwrap();
+        assert_eq!(top.apply(3), u64::MAX - 6);
+        assert_eq!(top.apply(u64::MAX), u64::MAX);
+        assert_eq!(top.then(&RangeMap::identity()), top);
+        assert_eq!(RangeMap::identity().then(&top), top);
+        assert_eq!(top.then(&RangeMap::identity()).apply(3), u64::MAX - 6);
+        assert!(RangeMap::from_mappings(&[mapping(u64::MAX - 5, 0, 10)]).is_err());
+        assert!(RangeMap::from_mappings(&[mapping(0, u64::MAX - 5, 10)]).is_err());
+
+        let (_, seeds) = Seeds::from_input("seeds: 18446744073709551615 7\n", SeedReading::Individual).unwrap();
+        assert_eq!(seeds.lowest_location(&top), Some((7, u64::MAX - 2)));
+        assert!(Seeds::from_input("seeds: 18446744073709551615 2\n", SeedReading::Ranges).is_err());
+        let (_, seeds) = Seeds::from_input("seeds: 18446744073709551615 1\n", SeedReading::Ranges).unwrap();
+        assert_eq!(seeds.lowest_location(&top), Some((u64::MAX, u64::MAX)));
+    }
+
+    #[test]
+    fn test_categories() {
+        let (rest, _) = Seeds::from_input(EXAMPLE, SeedReading::Ranges).unwrap();
+        let almanac = almanac_from_input(rest);
+        let chain: Vec<(&str, &str)> = almanac.stages.iter().map(|s| (s.source, s.destination)).collect();
+        assert_eq!(chain[0], ("seed", "soil"));
+        assert_eq!(chain[6], ("humidity", "location"));
+        let trace = |seed| ["seed", "soil", "fertilizer", "water", "light", "temperature", "humidity", "location"]
+            .map(|category| almanac.category_value(seed, category).unwrap());
+        assert_eq!(trace(79), [79, 81, 81, 81, 74, 78, 78, 82]);
+        assert_eq!(trace(14), [14, 14, 53, 49, 42, 42, 43, 43]);
+        assert_eq!(almanac.category_value(79, "fuel"), None);
+        assert!(almanac.map_to("fuel").is_none());
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(Stage::new("seed", "soil", vec![mapping(0, 10, 5), mapping(50, 14, 3)]).is_err());
+        assert!(Stage::new("seed", "soil", vec![mapping(0, 10, 5), mapping(50, 15, 3)]).is_ok());
+        let stage = |source, destination| Stage::new(source, destination, vec![mapping(0, 10, 5)]).unwrap();
+        assert_eq!(
+            Almanac::from_stages(vec![stage("seed", "soil"), stage("water", "location")]).err(),
+            Some("Expected a map from soil but found water-to-location".to_string()),
+        );
+        assert_eq!(
+            Almanac::from_stages(vec![stage("seed", "soil")]).err(),
+            Some("Almanac ends at soil instead of location".to_string()),
+        );
+    }
 }
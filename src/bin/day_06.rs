@@ -1,4 +1,4 @@
-use std::time::Instant;
+use std::{time::Instant, ops::RangeInclusive};
 
 use aoc2023::read_input;
 use nom::{sequence::tuple, character::complete::{multispace1, newline, digit1}, multi::separated_list1, bytes::complete::tag, IResult};
@@ -17,24 +17,203 @@ fn parse_input(input: &str) -> IResult<&str, (Vec<&str>, Vec<&str>)> {
     Ok((i, (times, distances)))
 }
 
+#[derive(Clone, Copy, Debug)]
+struct Race {
+    time: u128,
+    record: u128,
+}
+
+#[derive(Clone, Debug)]
+struct RaceAnalysis {
+    race: Race,
+    winning_holds: Option<RangeInclusive<u128>>,
+    optimal_hold: u128,
+    // `None` when the distance overflows a u128, which only happens for times beyond 2^64
+    max_distance: Option<u128>,
+    // how far the optimal hold beats the record by, zero if it cannot be beaten
+    margin: Option<u128>,
+}
+
+/// Floor of the square root, by Newton's method so no float rounding is involved.
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    // start from a power of two at or above the root so the iteration decreases monotonically
+    let mut x = 1u128 << (128 - n.leading_zeros()).div_ceil(2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+impl Race {
+    /// Whether holding for `hold` beats the record, i.e. `hold * (time - hold) > record`. Compared
+    /// by division so it cannot overflow.
+    pub fn wins(&self, hold: u128) -> bool {
+        hold > 0 && hold < self.time && self.time - hold > self.record / hold
+    }
+
+    /// Shortest winning hold time, if any. The roots of `h * (T - h) = D` give an estimate which
+    /// is then nudged to the exact boundary; when `T^2` overflows a binary search is used instead.
+    fn min_winning_hold(&self) -> Option<u128> {
+        let best = self.time / 2;
+        if !self.wins(best) {
+            return None;
+        }
+        let discriminant = self.time.checked_mul(self.time)
+            .and_then(|t2| self.record.checked_mul(4).and_then(|d4| t2.checked_sub(d4)));
+        let mut hold = match discriminant {
+            Some(discriminant) => (self.time - isqrt(discriminant)) / 2,
+            None => {
+                let (mut low, mut high) = (0, best);
+                while low < high {
+                    let mid = low + (high - low) / 2;
+                    if self.wins(mid) {
+                        high = mid;
+                    } else {
+                        low = mid + 1;
+                    }
+                }
+                low
+            },
+        };
+        while hold > 0 && self.wins(hold - 1) {
+            hold -= 1;
+        }
+        while !self.wins(hold) {
+            hold += 1;
+        }
+        Some(hold)
+    }
+
+    pub fn winning_holds(&self) -> Option<RangeInclusive<u128>> {
+        // winning holds are symmetric around time / 2
+        self.min_winning_hold().map(|min_hold| min_hold..=self.time - min_hold)
+    }
+
+    pub fn count_winning(&self) -> u128 {
+        match self.winning_holds() {
+            Some(holds) => holds.end() - holds.start() + 1,
+            None => 0,
+        }
+    }
+
+    pub fn analyze(&self) -> RaceAnalysis {
+        let optimal_hold = self.time / 2;
+        let max_distance = optimal_hold.checked_mul(self.time - optimal_hold);
+        RaceAnalysis {
+            race: *self,
+            winning_holds: self.winning_holds(),
+            optimal_hold,
+            max_distance,
+            margin: max_distance.map(|distance| distance.saturating_sub(self.record)),
+        }
+    }
+}
+
+fn print_analysis_table(analyses: &[RaceAnalysis]) {
+    let or_overflow = |value: Option<u128>| value.map_or("overflow".to_string(), |value| value.to_string());
+    println!("{:>16} {:>16} {:>16} {:>16} {:>16} {:>16} {:>16} {:>16}",
+        "time", "record", "min hold", "max hold", "ways to win", "optimal hold", "max distance", "margin");
+    for analysis in analyses {
+        let (min_hold, max_hold) = match &analysis.winning_holds {
+            Some(holds) => (holds.start().to_string(), holds.end().to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!("{:>16} {:>16} {:>16} {:>16} {:>16} {:>16} {:>16} {:>16}",
+            analysis.race.time, analysis.race.record, min_hold, max_hold, analysis.race.count_winning(),
+            analysis.optimal_hold, or_overflow(analysis.max_distance), or_overflow(analysis.margin));
+    }
+}
+
+/// Reads the columns as separate races (part 1) or, with `kerned`, as one race whose digits were
+/// split by bad kerning (part 2).
+fn races_from_columns(time_strs: &[&str], record_strs: &[&str], kerned: bool) -> Vec<Race> {
+    if kerned {
+        vec![Race {
+            time: time_strs.join("").parse().unwrap(),
+            record: record_strs.join("").parse().unwrap(),
+        }]
+    } else {
+        time_strs.iter().zip(record_strs.iter())
+            .map(|(time, record)| Race { time: time.parse().unwrap(), record: record.parse().unwrap() })
+            .collect()
+    }
+}
+
 pub fn main() {
     let start_time = Instant::now();
     let input = read_input("06");
 
     let (_, (time_strs, record_strs)) = parse_input(&input).unwrap();
 
-    let time = time_strs.join("").parse::<u64>().unwrap();
-    let record = record_strs.join("").parse::<u64>().unwrap();
+    let races = races_from_columns(&time_strs, &record_strs, false);
+    let kerned_race = races_from_columns(&time_strs, &record_strs, true)[0];
+    let product: u128 = races.iter().map(Race::count_winning).product();
+    let num_winning: u128 = kerned_race.count_winning();
 
-    let mut num_winning = 0;
-    for hold_time in 1..time {
-        let time_to_race = time - hold_time;
-        let distance_traveled = time_to_race * hold_time;
-        if distance_traveled > record {
-            num_winning += 1;
+    println!("Total time: {:?}", start_time.elapsed());
+    let analyses: Vec<RaceAnalysis> = races.iter().chain([kerned_race].iter()).map(Race::analyze).collect();
+    print_analysis_table(&analyses);
+    println!("{}", product);
+    println!("{}", num_winning);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brute_force(race: &Race) -> u128 {
+        (0..=race.time).filter(|&hold| hold * (race.time - hold) > race.record).count() as u128
+    }
+
+    #[test]
+    fn test_count_winning() {
+        for time in 0..60 {
+            for record in 0..(time * time / 4 + 2) {
+                let race = Race { time, record };
+                assert_eq!(race.count_winning(), brute_force(&race), "{:?}", race);
+            }
         }
+        assert_eq!(Race { time: 71530, record: 940200 }.count_winning(), 71503);
+        assert_eq!(Race { time: 30, record: 200 }.winning_holds(), Some(11..=19));
     }
 
-    println!("Total time: {:?}", start_time.elapsed());
-    println!("{}", num_winning);
+    #[test]
+    fn test_analyze() {
+        let analyses: Vec<RaceAnalysis> = [(7, 9), (15, 40), (30, 200)].iter()
+            .map(|&(time, record)| Race { time, record }.analyze())
+            .collect();
+        let summary: Vec<_> = analyses.iter()
+            .map(|a| (a.winning_holds.clone(), a.optimal_hold, a.max_distance, a.margin))
+            .collect();
+        assert_eq!(summary, vec![
+            (Some(2..=5), 3, Some(12), Some(3)),
+            (Some(4..=11), 7, Some(56), Some(16)),
+            (Some(11..=19), 15, Some(225), Some(25)),
+        ]);
+        let unbeatable = Race { time: 7, record: 12 }.analyze();
+        assert_eq!((unbeatable.winning_holds, unbeatable.margin), (None, Some(0)));
+        // the best distance no longer fits, but the winning interval still does
+        let huge = Race { time: 1 << 66, record: 10 }.analyze();
+        assert_eq!((huge.max_distance, huge.margin), (None, None));
+        assert_eq!(huge.winning_holds, Some(1..=(1 << 66) - 1));
+    }
+
+    #[test]
+    fn test_huge_race() {
+        // T^2 overflows, so this goes through the binary search
+        let time = (1u128 << 65) - 1;
+        let best = time / 2;
+        let race = Race { time, record: best * (time - best) - 1 };
+        assert!(!race.wins(best - 1));
+        assert_eq!(race.count_winning(), 2);
+        let race = Race { time, record: time - 2 };
+        assert_eq!(race.count_winning(), time - 1);
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+    }
 }
@@ -1,13 +1,12 @@
 use aoc2023::read_input;
-use enum_map::{Enum, enum_map};
-use nom::{IResult, multi::separated_list1, character::complete::{newline, u32 as parse_u32, multispace1}, sequence::separated_pair, bytes::complete::take};
+use enum_map::{Enum, EnumMap};
+use nom::{IResult, multi::separated_list1, character::complete::{newline, u32 as parse_u32, multispace1, one_of}, sequence::{separated_pair, pair}, bytes::complete::{take, tag}, combinator::{map, verify}};
 
-use std::{cmp::Ordering, time::Instant};
+use std::{cmp::Ordering, time::Instant, env};
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Enum)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug, Enum)]
 #[repr(u8)]
 enum Card {
-    J,
     TWO,
     THREE,
     FOUR,
@@ -17,6 +16,7 @@ enum Card {
     EIGHT,
     NINE,
     TEN,
+    J,
     Q,
     K,
     A,
@@ -43,7 +43,35 @@ impl From<u8> for Card {
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+/// Standard ranking, or one card acting as a wildcard. The wildcard is taken out of the usual
+/// order and placed at `rank`, from 0 (below every other card) to 12 (above every other card).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Rules {
+    Standard,
+    Wildcard { card: Card, rank: u8 },
+}
+
+impl Rules {
+    /// Part 2's rules: jacks are wild and rank lowest.
+    const JOKERS: Rules = Rules::Wildcard { card: Card::J, rank: 0 };
+}
+
+impl Card {
+    /// Strength used when comparing hands of the same type position by position.
+    fn strength(self, rules: Rules) -> u8 {
+        match rules {
+            Rules::Standard => self as u8,
+            Rules::Wildcard { card, rank } if self == card => rank,
+            Rules::Wildcard { card, rank } => {
+                // position among the other twelve cards, then make room for the wildcard
+                let position = self as u8 - (self > card) as u8;
+                position + (position >= rank) as u8
+            },
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 #[repr(u8)]
 enum HandType {
     HighCard,
@@ -58,101 +86,56 @@ enum HandType {
 #[derive(PartialEq, Eq, Debug)]
 struct Hand{
     cards: [Card; 5],
+    strengths: [u8; 5],
     hand_type: HandType,
 }
 
 impl HandType {
-    fn from_cards(cards: [Card; 5]) -> HandType {
-        let mut card_counts = enum_map! {
-            Card::TWO => (Card::TWO, 0u32),
-            Card::THREE => (Card::THREE, 0u32),
-            Card::FOUR => (Card::FOUR, 0u32),
-            Card::FIVE => (Card::FIVE, 0u32),
-            Card::SIX => (Card::SIX, 0u32),
-            Card::SEVEN => (Card::SEVEN, 0u32),
-            Card::EIGHT => (Card::EIGHT, 0u32),
-            Card::NINE => (Card::NINE, 0u32),
-            Card::TEN => (Card::TEN, 0u32),
-            Card::J => (Card::J, 0u32),
-            Card::Q => (Card::Q, 0u32),
-            Card::K => (Card::K, 0u32),
-            Card::A => (Card::A, 0u32),
-        };
+    /// Classifies a hand of any size from its sorted card multiplicities. Adding every wildcard to
+    /// the largest group of natural cards always produces the best possible type.
+    fn from_cards(cards: &[Card], rules: Rules) -> HandType {
+        let mut card_counts: EnumMap<Card, usize> = EnumMap::default();
+        let mut wild_count = 0;
         for &card in cards.iter() {
-            card_counts[card].1 += 1;
+            match rules {
+                Rules::Wildcard { card: wildcard, .. } if card == wildcard => wild_count += 1,
+                _ => card_counts[card] += 1,
+            }
         }
-        let (_, j_count) = card_counts[Card::J];
-        let counts_list = card_counts.as_mut_slice();
-        counts_list.sort_by_key(|(_, v)| *v);
-        /*
-        match (counts_list) {
-            ([.., (not_j, max)]) if not_j != &Card::J && *max + j_count == 5 =>
-            ([.., (_, 4)], 0) | (_, 3) => HandType::FourKind,
-            ([.., (not_j, 3)], 1) if not_j != &Card::J => HandType::FourKind,
-            ([.., (_, 2), (_, 3)], 0) => HandType::FullHouse,
-            ([.., (not_j1, 1), (not_j2, 3)], 1) if not_j1 != &Card::J && not_j2 != &Card::J => HandType::FullHouse,
-            ([.., (not_j1, 2), (not_j2, 2)], 1) if not_j1 != &Card::J && not_j2 != &Card::J => HandType::FullHouse,
-            ([.., (_, 3)], _) => HandType::ThreeKind,
-            ([.., (not_j, 2)], _) => HandType::ThreeKind,
-            ([.., (_, 2), (_, 2)], _) => HandType::TwoPair,
-            ([.., (_, 2)], _) => HandType::OnePair,
+        let mut multiplicities = card_counts.into_array();
+        multiplicities.sort_unstable_by(|a, b| b.cmp(a));
+        multiplicities[0] += wild_count;
+        match (multiplicities[0], multiplicities[1]) {
+            (5.., _) => HandType::FiveKind,
+            (4, _) => HandType::FourKind,
+            (3, 2..) => HandType::FullHouse,
+            (3, _) => HandType::ThreeKind,
+            (2, 2) => HandType::TwoPair,
+            (2, _) => HandType::OnePair,
             _ => HandType::HighCard,
         }
-        */
-        let &[.., (_, count2), (_, count1)] = card_counts.as_array();
-        match j_count {
-            0 => match (count1, count2) {
-                (5, _) => HandType::FiveKind,
-                (4, _) => HandType::FourKind,
-                (3, 2) => HandType::FullHouse,
-                (3, _) => HandType::ThreeKind,
-                (2, 2) => HandType::TwoPair,
-                (2, 1) => HandType::OnePair,
-                _ => HandType::HighCard,
-            },
-            1 => {
-                match (count1, count2) {
-                    (4, _) => HandType::FiveKind,
-                    (3, _) => HandType::FourKind,
-                    (2, 2) => HandType::FullHouse,
-                    (2, 1) => HandType::ThreeKind,
-                    _ => HandType::OnePair,
-                }
-            },
-            2 => {
-                match (count1, count2) {
-                    (3, _) => HandType::FiveKind,
-                    (2, 2) => HandType::FourKind,
-                    _ => HandType::ThreeKind,
-                }
-            },
-            3 => {
-                match (count1, count2) {
-                    (3, 2) => HandType::FiveKind,
-                    _ => HandType::FourKind,
-                }
-            }
-            4 | 5 => HandType::FiveKind,
-            _ => panic!("Unexpected number of J"),
-        }
     }
 }
 
 impl Hand {
-    pub fn from_input(input: &str) -> IResult<&str, Hand> {
-        let (i, cards_str) = take(5usize)(input)?;
-        let mut cards = [Card::A; 5];
-        let cards_bytes = cards_str.as_bytes();
-        for (&byte, card_to_set) in cards_bytes.iter().zip(cards.iter_mut()) {
-            let card: Card = byte.into();
-            *card_to_set = card;
-        }
-        let hand_type = HandType::from_cards(cards);
-        Ok((i, Hand {
+    pub fn new(cards: [Card; 5], rules: Rules) -> Hand {
+        Hand {
             cards,
-            hand_type,
-        }))
+            strengths: cards.map(|card| card.strength(rules)),
+            hand_type: HandType::from_cards(&cards, rules),
+        }
+    }
+}
+
+fn cards_from_input(input: &str) -> IResult<&str, [Card; 5]> {
+    let (i, cards_str) = take(5usize)(input)?;
+    let mut cards = [Card::A; 5];
+    let cards_bytes = cards_str.as_bytes();
+    for (&byte, card_to_set) in cards_bytes.iter().zip(cards.iter_mut()) {
+        let card: Card = byte.into();
+        *card_to_set = card;
     }
+    Ok((i, cards))
 }
 impl Ord for Hand {
     fn cmp(&self, other: &Self) -> Ordering {
@@ -160,7 +143,7 @@ impl Ord for Hand {
         if type_order != Ordering::Equal {
             type_order
         } else {
-            self.cards.cmp(&other.cards)
+            self.strengths.cmp(&other.strengths)
         }
     }
 }
@@ -170,14 +153,128 @@ impl PartialOrd for Hand {
     }
 }
 
-fn parse_hands_and_bids(input: &str) -> IResult<&str, Vec<(Hand, u32)>> {
-    separated_list1(newline, separated_pair(Hand::from_input, multispace1, parse_u32))(input)
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Suit {
+    Clubs,
+    Diamonds,
+    Hearts,
+    Spades,
 }
-pub fn main() {
-    let start_time = Instant::now();
-    let input = read_input("07");
 
-    let (_, mut hands_bids) = parse_hands_and_bids(&input).unwrap();
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+enum PokerCategory {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeKind,
+    Straight,
+    Flush,
+    FullHouse,
+    FourKind,
+    StraightFlush,
+}
+
+/// A real five-card poker hand. Hands of the same category are compared by kickers: ranks ordered
+/// by group size and then by rank, so position in the dealt hand doesn't matter.
+#[derive(PartialEq, Eq, Debug)]
+struct PokerHand {
+    cards: [(Card, Suit); 5],
+    category: PokerCategory,
+    kickers: [Card; 5],
+}
+
+impl From<char> for Suit {
+    fn from(value: char) -> Self {
+        match value {
+            'C' => Suit::Clubs,
+            'D' => Suit::Diamonds,
+            'H' => Suit::Hearts,
+            'S' => Suit::Spades,
+            _ => panic!("Invalid suit char: {}", value),
+        }
+    }
+}
+
+impl PokerHand {
+    pub fn new(cards: [(Card, Suit); 5]) -> PokerHand {
+        let mut rank_counts: EnumMap<Card, usize> = EnumMap::default();
+        for &(rank, _) in cards.iter() {
+            rank_counts[rank] += 1;
+        }
+        let mut groups: Vec<(usize, Card)> = rank_counts.iter()
+            .filter(|(_, &count)| count > 0)
+            .map(|(rank, &count)| (count, rank))
+            .collect();
+        groups.sort_unstable_by(|a, b| b.cmp(a));
+        let mut kickers = [Card::TWO; 5];
+        for (kicker, rank) in kickers.iter_mut().zip(groups.iter().flat_map(|&(count, rank)| std::iter::repeat_n(rank, count))) {
+            *kicker = rank;
+        }
+
+        let is_flush = cards.iter().all(|&(_, suit)| suit == cards[0].1);
+        let mut is_straight = groups.len() == 5 && kickers[0] as u8 - kickers[4] as u8 == 4;
+        if kickers == [Card::A, Card::FIVE, Card::FOUR, Card::THREE, Card::TWO] {
+            // the wheel: ace plays low
+            is_straight = true;
+            kickers.rotate_left(1);
+        }
+        let category = match (is_straight, is_flush, groups[0].0, groups.get(1).map_or(0, |g| g.0)) {
+            (true, true, _, _) => PokerCategory::StraightFlush,
+            (_, _, 4, _) => PokerCategory::FourKind,
+            (_, _, 3, 2) => PokerCategory::FullHouse,
+            (_, true, _, _) => PokerCategory::Flush,
+            (true, _, _, _) => PokerCategory::Straight,
+            (_, _, 3, _) => PokerCategory::ThreeKind,
+            (_, _, 2, 2) => PokerCategory::TwoPair,
+            (_, _, 2, _) => PokerCategory::OnePair,
+            _ => PokerCategory::HighCard,
+        };
+        PokerHand { cards, category, kickers }
+    }
+
+    /// Parses hands like `AS KD QH JC TS`. A hand from a single deck can't hold the same card twice,
+    /// so repeated cards are rejected.
+    pub fn from_input(input: &str) -> IResult<&str, PokerHand> {
+        let card = pair(
+            map(one_of("23456789TJQKA"), |rank| Card::from(rank as u8)),
+            map(one_of("CDHS"), Suit::from),
+        );
+        let (i, cards) = verify(separated_list1(tag(" "), card), |cards: &Vec<(Card, Suit)>| {
+            cards.len() == 5 && cards.iter().enumerate().all(|(idx, card)| !cards[..idx].contains(card))
+        })(input)?;
+        let mut hand_cards = [(Card::A, Suit::Spades); 5];
+        hand_cards.copy_from_slice(&cards);
+        Ok((i, PokerHand::new(hand_cards)))
+    }
+}
+
+impl Ord for PokerHand {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.category.cmp(&other.category).then_with(|| self.kickers.cmp(&other.kickers))
+    }
+}
+impl PartialOrd for PokerHand {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn parse_hands_and_bids(input: &str) -> IResult<&str, Vec<([Card; 5], u32)>> {
+    separated_list1(newline, separated_pair(cards_from_input, multispace1, parse_u32))(input)
+}
+
+fn parse_poker_hands_and_bids(input: &str) -> IResult<&str, Vec<(PokerHand, u32)>> {
+    separated_list1(newline, separated_pair(PokerHand::from_input, multispace1, parse_u32))(input)
+}
+
+fn total_winnings(cards_bids: &[([Card; 5], u32)], rules: Rules) -> u64 {
+    let hands_bids: Vec<(Hand, u32)> = cards_bids.iter()
+        .map(|&(cards, bid)| (Hand::new(cards, rules), bid))
+        .collect();
+    ranked_winnings(hands_bids)
+}
+
+fn ranked_winnings<H: Ord>(mut hands_bids: Vec<(H, u32)>) -> u64 {
     hands_bids.sort_by(|(hand, _), (hand2, _)| hand.cmp(hand2));
 
     let mut sum = 0u64;
@@ -185,6 +282,119 @@ pub fn main() {
         let winnings = bid as u64 * rank as u64;
         sum += winnings;
     }
+    sum
+}
+
+pub fn main() {
+    let start_time = Instant::now();
+    let input = read_input("07");
+
+    // `poker` evaluates real poker hands written like `AS KD QH JC TS 765` instead
+    if env::args().nth(1).as_deref() == Some("poker") {
+        let (_, hands_bids) = parse_poker_hands_and_bids(&input).unwrap();
+        let winnings = ranked_winnings(hands_bids);
+        println!("Total time: {:?}", start_time.elapsed());
+        println!("{}", winnings);
+        return;
+    }
+
+    let (_, cards_bids) = parse_hands_and_bids(&input).unwrap();
+    let standard = total_winnings(&cards_bids, Rules::Standard);
+    let jokers = total_winnings(&cards_bids, Rules::JOKERS);
     println!("Total time: {:?}", start_time.elapsed());
-    println!("{}", sum);
+    println!("{}", standard);
+    println!("{}", jokers);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Best type reachable by substituting every possible card for each wildcard.
+    fn brute_force(cards: &mut [Card; 5], wildcard: Card, from: usize) -> HandType {
+        match cards[from..].iter().position(|&card| card == wildcard) {
+            None => HandType::from_cards(cards, Rules::Standard),
+            Some(offset) => {
+                let idx = from + offset;
+                let mut best = HandType::HighCard;
+                for substitute in (0..Card::LENGTH).map(Card::from_usize) {
+                    cards[idx] = substitute;
+                    // the substitute may equal the wildcard, so resume after this position
+                    best = best.max(brute_force(cards, wildcard, idx + 1));
+                }
+                cards[idx] = wildcard;
+                best
+            },
+        }
+    }
+
+    #[test]
+    fn test_standard_types() {
+        let hand = |s: &str| HandType::from_cards(&cards_from_input(s).unwrap().1, Rules::Standard);
+        assert_eq!(hand("AAAAA"), HandType::FiveKind);
+        assert_eq!(hand("AA8AA"), HandType::FourKind);
+        assert_eq!(hand("23332"), HandType::FullHouse);
+        assert_eq!(hand("TTT98"), HandType::ThreeKind);
+        assert_eq!(hand("23432"), HandType::TwoPair);
+        assert_eq!(hand("A23A4"), HandType::OnePair);
+        assert_eq!(hand("23456"), HandType::HighCard);
+        assert_eq!(HandType::from_cards(&[Card::A, Card::A, Card::K, Card::K, Card::K, Card::K], Rules::Standard), HandType::FourKind);
+        assert_eq!(HandType::from_cards(&[Card::J; 7], Rules::JOKERS), HandType::FiveKind);
+        assert_eq!(HandType::from_cards(&[], Rules::Standard), HandType::HighCard);
+    }
+
+    #[test]
+    fn test_wildcard_rank() {
+        let strengths = |rules: Rules| [Card::TWO, Card::J, Card::Q, Card::A].map(|card| card.strength(rules));
+        assert_eq!(strengths(Rules::Standard), [0, 9, 10, 12]);
+        assert_eq!(strengths(Rules::JOKERS), [1, 0, 10, 12]);
+        assert_eq!(strengths(Rules::Wildcard { card: Card::J, rank: 12 }), [0, 12, 9, 11]);
+        assert_eq!(strengths(Rules::Wildcard { card: Card::J, rank: 9 }), strengths(Rules::Standard));
+        assert_eq!(strengths(Rules::Wildcard { card: Card::A, rank: 0 }), [1, 10, 11, 0]);
+        let hand = |s: &str, rules| Hand::new(cards_from_input(s).unwrap().1, rules);
+        let high_wild = Rules::Wildcard { card: Card::J, rank: 12 };
+        assert!(hand("JKKK2", high_wild) > hand("QQQQ2", high_wild));
+        assert!(hand("JKKK2", Rules::JOKERS) < hand("QQQQ2", Rules::JOKERS));
+    }
+
+    #[test]
+    fn test_poker_hands() {
+        let hand = |s: &str| PokerHand::from_input(s).unwrap().1;
+        assert_eq!(hand("AS 2D 3H 4C 5S").category, PokerCategory::Straight);
+        assert!(hand("AS 2D 3H 4C 5S") < hand("6S 2D 3H 4C 5S"));
+        assert!(hand("TS JS QS KS AS") > hand("9H 9D 9S 9C AS"));
+        assert!(hand("2H 3H 4H 5H 7H") > hand("TS JD QH KC AS"));
+        assert!(hand("KH KD 2S 2C 3H") < hand("KS KC 3S 3C 2H"));
+        assert!(hand("AH 9D 9S 4C 3H") > hand("9H 9C KS QC JH"));
+        assert_eq!(hand("QH QD QS 4C 4H").kickers, [Card::Q, Card::Q, Card::Q, Card::FOUR, Card::FOUR]);
+        assert_eq!(hand("2H 7D 9S QC KH").cmp(&hand("KS 7C 2D QS 9D")), Ordering::Equal);
+        assert!(PokerHand::from_input("AS KD QH JC").is_err());
+        assert!(PokerHand::from_input("AS AS AS AS AS").is_err());
+        assert!(PokerHand::from_input("AS AS AS AS KS").is_err());
+        assert!(PokerHand::from_input("2H 3D 2C 4S 2H").is_err());
+        assert_eq!(hand("AS AD AH AC KS").category, PokerCategory::FourKind);
+    }
+
+    #[test]
+    fn test_wildcards_exhaustive() {
+        for wildcard in [Card::J, Card::TWO] {
+            // the type only depends on which cards are held, so brute force each multiset once
+            let mut brute_forced: HashMap<[Card; 5], HandType> = HashMap::new();
+            for hand_idx in 0..Card::LENGTH.pow(5) {
+                let mut cards = [Card::A; 5];
+                let mut remaining = hand_idx;
+                for card in cards.iter_mut() {
+                    *card = Card::from_usize(remaining % Card::LENGTH);
+                    remaining /= Card::LENGTH;
+                }
+                let mut sorted = cards;
+                sorted.sort_unstable();
+                let expected = *brute_forced.entry(sorted)
+                    .or_insert_with(|| brute_force(&mut sorted.clone(), wildcard, 0));
+                assert_eq!(HandType::from_cards(&cards, Rules::Wildcard { card: wildcard, rank: 0 }), expected, "{:?}", cards);
+            }
+        }
+    }
 }
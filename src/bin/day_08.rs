@@ -53,42 +53,354 @@ fn lcm(val1: usize, val2: usize) -> usize {
     multiple / gcd(val1, val2) as usize
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum WalkError {
+    MissingNode(String),
+    // the walk settled into a loop at `loop_start` without ever passing a goal
+    GoalUnreachable { loop_start: usize, period: usize },
+}
+
+/// Outcome of walking every start node at once: each walker's own step count to a goal, and the
+/// first step at which all of them stand on goals together.
+#[derive(Debug)]
+struct WalkReport<'a> {
+    starts: Vec<usize>,
+    walkers: Vec<(&'a str, Result<usize, WalkError>)>,
+    together: Option<usize>,
+    lcm_shortcut: bool,
+}
+
+/// The network with node names interned to indices, so walking never hashes a string.
+struct Network<'a> {
+    names: Vec<&'a str>,
+    successors: Vec<[usize; 2]>,
+    // 0 for `L` and 1 for `R`, indexing into `successors`
+    instructions: Vec<usize>,
+    // node reached from each node by following the whole instruction string once
+    full_pass: Vec<usize>,
+}
+
+/// When a ghost's walk settles into a loop over `(node, instruction index)` states, and the steps
+/// at which it stands on a goal node before and during that loop.
+#[derive(Debug)]
+struct GhostCycle {
+    // step at which the loop is first entered
+    pre_period: usize,
+    period: usize,
+    // goal steps before the loop; these never recur
+    transient_hits: Vec<usize>,
+    // goal steps in the first trip around the loop; each recurs every `period` steps
+    cycle_hits: Vec<usize>,
+}
+
+impl GhostCycle {
+    pub fn analyze(start: usize, network: &Network, is_goal: &[bool]) -> GhostCycle {
+        let instructions_len = network.instructions.len();
+        // step at which each (node, instruction index) state was first reached
+        let mut first_seen = vec![usize::MAX; network.names.len() * instructions_len];
+        let mut hits = Vec::new();
+        let mut node = start;
+        let mut step = 0;
+        loop {
+            let instr_idx = step % instructions_len;
+            let state = node * instructions_len + instr_idx;
+            if first_seen[state] != usize::MAX {
+                let pre_period = first_seen[state];
+                let split = hits.partition_point(|&hit| hit < pre_period);
+                let cycle_hits = hits.split_off(split);
+                return GhostCycle { pre_period, period: step - pre_period, transient_hits: hits, cycle_hits };
+            }
+            first_seen[state] = step;
+            if is_goal[node] {
+                hits.push(step);
+            }
+            node = network.step(node, instr_idx);
+            step += 1;
+        }
+    }
+
+    /// First step (at least 1) on a goal, or why there isn't one.
+    pub fn first_goal(&self) -> Result<usize, WalkError> {
+        // a goal at step 0 next recurs a whole period later, after any other loop goals
+        self.transient_hits.iter().copied()
+            .chain(self.cycle_hits.iter().map(|&hit| if hit == 0 { self.period } else { hit }))
+            .filter(|&step| step >= 1)
+            .min()
+            .ok_or(WalkError::GoalUnreachable { loop_start: self.pre_period, period: self.period })
+    }
+
+    pub fn is_at_goal(&self, step: usize) -> bool {
+        if step < self.pre_period {
+            self.transient_hits.contains(&step)
+        } else {
+            let offset = self.pre_period + (step - self.pre_period) % self.period;
+            self.cycle_hits.contains(&offset)
+        }
+    }
+
+    /// Whether this ghost reaches a goal at exactly the positive multiples of its period, the
+    /// structure the puzzle inputs are built with and the only case where taking the lcm is
+    /// correct. The loop's single goal must be at step 0 or `period`; a later multiple would mean
+    /// the earlier multiples fall in the transient and aren't goals.
+    pub fn lcm_shortcut_applies(&self) -> bool {
+        self.transient_hits.iter().all(|&hit| hit == 0)
+            && self.cycle_hits.len() == 1
+            && (self.cycle_hits[0] == 0 || self.cycle_hits[0] == self.period)
+    }
+}
+
+/// Solves `x = r1 (mod m1)` and `x = r2 (mod m2)` for moduli that need not be coprime, returning
+/// the combined residue and modulus, or `None` if the congruences are incompatible.
+fn crt(r1: u128, m1: u128, r2: u128, m2: u128) -> Option<(u128, u128)> {
+    // extended Euclid: finds g = gcd(m1, m2) and x with m1 * x = g (mod m2)
+    let (mut old_r, mut r) = (m1 as i128, m2 as i128);
+    let (mut old_x, mut x) = (1i128, 0i128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_x, x) = (x, old_x - quotient * x);
+    }
+    let g = old_r as u128;
+    let diff = (r2 as i128 - r1 as i128).rem_euclid(m2 as i128) as u128;
+    if !diff.is_multiple_of(g) {
+        return None;
+    }
+    let m2_reduced = m2 / g;
+    let inverse = old_x.rem_euclid(m2_reduced as i128) as u128;
+    let k = (diff / g) % m2_reduced * inverse % m2_reduced;
+    let modulus = m1 * m2_reduced;
+    Some(((r1 + m1 * k) % modulus, modulus))
+}
+
+/// First step (at least 1) at which every ghost is on a goal at once, or `None` if that never
+/// happens.
+fn first_common_goal(cycles: &[GhostCycle]) -> Option<usize> {
+    if cycles.iter().all(GhostCycle::lcm_shortcut_applies) {
+        return cycles.iter().map(|c| c.period).reduce(lcm);
+    }
+    // before every ghost is looping, any common step must be one of the first ghost's goal steps
+    let all_looping = cycles.iter().map(|c| c.pre_period).max()?.max(1);
+    let first = &cycles[0];
+    let early_candidates = first.transient_hits.iter().copied()
+        .chain(first.cycle_hits.iter().flat_map(|&hit| (hit..all_looping).step_by(first.period)))
+        .filter(|&step| step >= 1 && step < all_looping);
+    if let Some(step) = early_candidates.filter(|&step| cycles.iter().all(|c| c.is_at_goal(step))).min() {
+        return Some(step);
+    }
+    // afterwards each ghost is periodic, so combine one loop goal from each with CRT
+    let mut congruences: Vec<(u128, u128)> = vec![(0, 1)];
+    for cycle in cycles {
+        let period = cycle.period as u128;
+        congruences = congruences.iter()
+            .flat_map(|&(residue, modulus)| cycle.cycle_hits.iter()
+                .filter_map(move |&hit| crt(residue, modulus, hit as u128 % period, period)))
+            .collect();
+        congruences.sort_unstable();
+        congruences.dedup();
+    }
+    congruences.into_iter()
+        .map(|(residue, modulus)| {
+            let start = all_looping as u128;
+            // smallest value at or after `start` with this residue
+            start + (residue + modulus - start % modulus) % modulus
+        })
+        .min()
+        .map(|step| step as usize)
+}
+
+impl<'a> Network<'a> {
+    pub fn new(instructions: &str, nodes: &[(&'a str, &'a str, &'a str)]) -> Result<Network<'a>, WalkError> {
+        let names: Vec<&str> = nodes.iter().map(|&(id, _, _)| id).collect();
+        let index: HashMap<&str, usize> = names.iter().enumerate().map(|(idx, &id)| (id, idx)).collect();
+        let lookup = |id: &str| index.get(id).copied().ok_or_else(|| WalkError::MissingNode(id.to_string()));
+        let mut successors = Vec::with_capacity(nodes.len());
+        for &(_, left, right) in nodes.iter() {
+            successors.push([lookup(left)?, lookup(right)?]);
+        }
+        let instructions: Vec<usize> = instructions.bytes().map(|instr| (instr == b'R') as usize).collect();
+        let full_pass = (0..names.len())
+            .map(|start| instructions.iter().fold(start, |node, &instr| successors[node][instr]))
+            .collect();
+        Ok(Network { names, successors, instructions, full_pass })
+    }
+
+    /// For each node, the steps within one pass over the instructions (1 to the pass length) that
+    /// land on a goal.
+    fn goal_offsets(&self, is_goal: &[bool]) -> Vec<Vec<usize>> {
+        (0..self.names.len())
+            .map(|start| {
+                let mut node = start;
+                let mut offsets = Vec::new();
+                for (offset, &instr) in (1..).zip(self.instructions.iter()) {
+                    node = self.successors[node][instr];
+                    if is_goal[node] {
+                        offsets.push(offset);
+                    }
+                }
+                offsets
+            })
+            .collect()
+    }
+
+    pub fn step(&self, node: usize, instr_idx: usize) -> usize {
+        self.successors[node][self.instructions[instr_idx]]
+    }
+
+    pub fn nodes_matching(&self, predicate: impl Fn(&str) -> bool) -> Vec<bool> {
+        self.names.iter().map(|name| predicate(name)).collect()
+    }
+
+    /// Steps every walker one full instruction pass at a time until they are all on goals at
+    /// once, giving up after `max_steps`. Useful when the cycle analysis can't take the lcm
+    /// shortcut and its answer should be checked directly.
+    pub fn simulate(&self, starts: &[usize], is_goal: &[bool], max_steps: usize) -> Option<usize> {
+        let goal_offsets = self.goal_offsets(is_goal);
+        let mut nodes = starts.to_vec();
+        let pass_len = self.instructions.len();
+        let mut pass_start = 0;
+        while pass_start < max_steps {
+            let (first, rest) = nodes.split_first()?;
+            let common = goal_offsets[*first].iter()
+                .find(|offset| rest.iter().all(|&node| goal_offsets[node].binary_search(offset).is_ok()));
+            if let Some(offset) = common {
+                return Some(pass_start + offset).filter(|&steps| steps <= max_steps);
+            }
+            for node in nodes.iter_mut() {
+                *node = self.full_pass[*node];
+            }
+            pass_start += pass_len;
+        }
+        None
+    }
+}
+
+/// Walks from every node matching `is_start` until nodes matching `is_goal` are reached.
+fn walk<'a>(network: &Network<'a>, is_start: &[bool], is_goal: &[bool]) -> WalkReport<'a> {
+    let starts: Vec<usize> = (0..network.names.len()).filter(|&node| is_start[node]).collect();
+    let cycles: Vec<GhostCycle> = starts.iter()
+        .map(|&start| GhostCycle::analyze(start, network, is_goal))
+        .collect();
+    let walkers = starts.iter().zip(cycles.iter())
+        .map(|(&start, cycle)| (network.names[start], cycle.first_goal()))
+        .collect();
+    let together = if cycles.is_empty() { None } else { first_common_goal(&cycles) };
+    let lcm_shortcut = cycles.iter().all(GhostCycle::lcm_shortcut_applies);
+    WalkReport { starts, walkers, together, lcm_shortcut }
+}
+
 pub fn main() {
     let start_time = Instant::now();
     let input = read_input("08");
     let (_, (instructions, nodes)) = parse_instructions_and_nodes(&input).unwrap();
 
-    let node_map: HashMap<&str, (&str, &str)> = nodes.into_iter().map(|(id, l, r)| (id, (l, r))).collect();
-
-    let mut current_node_ids: Vec<&str> = node_map.keys().map(|k| *k).filter(|k| k.ends_with("A")).collect();
-    let mut num_steps = 0;
-    let mut starting_distance_to_z: Vec<usize> = vec![0; current_node_ids.len()];
-    loop {
-        for instr in instructions.as_bytes() {
-            for current_node_id in current_node_ids.iter_mut() {
-                let current_node = node_map[current_node_id];
-                if *instr == b'L' {
-                    *current_node_id = current_node.0;
-                } else {
-                    *current_node_id = current_node.1;
-                }
+    let network = Network::new(instructions, &nodes).unwrap();
 
-            }
-            num_steps += 1;
-            for (idx, id) in current_node_ids.iter().enumerate() {
-                if id.ends_with("Z") {
-                    if starting_distance_to_z[idx] == 0 {
-                        starting_distance_to_z[idx] = num_steps;
-                        if !starting_distance_to_z.contains(&0) {
-                            let total_lcm = starting_distance_to_z.into_iter().reduce(lcm).unwrap();
-                            println!("Total time: {:?}", start_time.elapsed());
-                            println!("{}", total_lcm);
-                            return
-                        }
-                    }
-                }
+    let single = walk(&network, &network.nodes_matching(|id| id == "AAA"), &network.nodes_matching(|id| id == "ZZZ"));
+    let ghost_goals = network.nodes_matching(|id| id.ends_with('Z'));
+    let ghosts = walk(&network, &network.nodes_matching(|id| id.ends_with('A')), &ghost_goals);
+    println!("Total time: {:?}", start_time.elapsed());
+    for (start, steps) in single.walkers.iter().chain(ghosts.walkers.iter()) {
+        match steps {
+            Ok(steps) => println!("{}: {} steps", start, steps),
+            Err(e) => println!("{}: {:?}", start, e),
+        }
+    }
+    match single.walkers.first() {
+        Some((_, Ok(steps))) => println!("{}", steps),
+        _ => println!("AAA never reaches ZZZ"),
+    }
+    match ghosts.together {
+        Some(steps) => println!("{}", steps),
+        None => println!("The ghosts never all reach a goal together"),
+    }
+
+    if !ghosts.lcm_shortcut {
+        // cross-check the congruence solution by brute force, one instruction pass at a time
+        const MAX_SIMULATED_STEPS: usize = 10_000_000_000;
+        if let Some(steps) = ghosts.together.filter(|&steps| steps <= MAX_SIMULATED_STEPS) {
+            assert_eq!(network.simulate(&ghosts.starts, &ghost_goals, steps), Some(steps));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt(3, 4, 5, 6), Some((11, 12)));
+        assert_eq!(crt(2, 3, 3, 5), Some((8, 15)));
+        assert_eq!(crt(1, 4, 2, 6), None);
+    }
+
+    #[test]
+    fn test_first_common_goal() {
+        let cycle = |pre_period, period, transient_hits, cycle_hits| GhostCycle { pre_period, period, transient_hits, cycle_hits };
+        // offsets that aren't multiples of the period make the lcm wrong
+        assert_eq!(first_common_goal(&[cycle(0, 4, vec![], vec![3]), cycle(0, 6, vec![], vec![5])]), Some(11));
+        // a goal before the loop is entered
+        assert_eq!(first_common_goal(&[cycle(5, 3, vec![2], vec![6]), cycle(0, 2, vec![], vec![0])]), Some(2));
+        assert_eq!(first_common_goal(&[cycle(0, 2, vec![], vec![0]), cycle(0, 2, vec![], vec![1])]), None);
+        assert_eq!(first_common_goal(&[cycle(1, 2, vec![], vec![2]), cycle(1, 3, vec![], vec![3])]), Some(6));
+        // the loop's goal is a multiple of the period, but the smaller multiples are still transient
+        let late = cycle(4, 2, vec![], vec![4]);
+        assert!(!late.lcm_shortcut_applies());
+        assert_eq!(first_common_goal(&[late, cycle(0, 2, vec![], vec![0])]), Some(4));
+    }
+
+    #[test]
+    fn test_first_goal_from_goal() {
+        let nodes = [("BBZ", "CCZ", "CCZ"), ("CCZ", "DDD", "DDD"), ("DDD", "BBZ", "BBZ")];
+        let network = Network::new("L", &nodes).unwrap();
+        let report = walk(&network, &network.nodes_matching(|id| id == "BBZ"), &network.nodes_matching(|id| id.ends_with('Z')));
+        assert_eq!(report.walkers, vec![("BBZ", Ok(1))]);
+        assert_eq!(report.together, Some(1));
+    }
 
+    #[test]
+    fn test_simulate_matches_stepping() {
+        let (_, (instructions, nodes)) = parse_instructions_and_nodes("LR
+
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+XXX = (XXX, XXX)").unwrap();
+        let network = Network::new(instructions, &nodes).unwrap();
+        for start in 0..network.names.len() {
+            let stepped = (0..network.instructions.len()).fold(start, |node, instr_idx| network.step(node, instr_idx));
+            assert_eq!(network.full_pass[start], stepped);
+        }
+        let starts: Vec<usize> = (0..network.names.len()).filter(|&node| network.names[node].ends_with('A')).collect();
+        let is_goal = network.nodes_matching(|id| id.ends_with('Z'));
+        // step every walker one instruction at a time until they are all on goals
+        let mut nodes = starts.clone();
+        let mut steps = 0;
+        while !nodes.iter().all(|&node| is_goal[node]) {
+            for node in nodes.iter_mut() {
+                *node = network.step(*node, steps % network.instructions.len());
             }
+            steps += 1;
         }
+        assert_eq!(steps, 6);
+        assert_eq!(network.simulate(&starts, &is_goal, 100), Some(steps));
+        assert_eq!(network.simulate(&starts, &is_goal, 5), None);
+    }
+
+    #[test]
+    fn test_walk_goal_in_transient() {
+        let nodes = [
+            ("A0", "A1", "A1"), ("A1", "A2", "A2"), ("A2", "A3", "A3"), ("A3", "A4Z", "A4Z"),
+            ("A4Z", "A5", "A5"), ("A5", "A4Z", "A4Z"), ("B0Z", "B1", "B1"), ("B1", "B0Z", "B0Z"),
+        ];
+        let network = Network::new("L", &nodes).unwrap();
+        let is_start = network.nodes_matching(|id| id == "A0" || id == "B0Z");
+        let report = walk(&network, &is_start, &network.nodes_matching(|id| id.ends_with('Z')));
+        assert_eq!(report.together, Some(4));
+        assert!(!report.lcm_shortcut);
     }
 }
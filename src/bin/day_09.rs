@@ -1,30 +1,162 @@
-use std::time::Instant;
+use std::{time::Instant, fmt::Display};
 
 use aoc2023::read_input;
-use nom::{multi::separated_list1, character::complete::{i32 as parse_i32, newline}, bytes::complete::tag, IResult};
+use nom::{multi::separated_list1, character::complete::{i64 as parse_i64, newline}, bytes::complete::tag, IResult};
 
 
-fn parse_sequences(input: &str) -> IResult<&str, Vec<Vec<i32>>> {
+fn parse_sequences(input: &str) -> IResult<&str, Vec<Vec<i64>>> {
     separated_list1(newline,
-        separated_list1(tag(" "), parse_i32))(input)
+        separated_list1(tag(" "), parse_i64))(input)
 }
 
-fn extrapolate_prev(sequence: Vec<i32>) -> i32 {
-    let mut diffs_vec = Vec::new();
-    let mut diffs: Vec<i32> = sequence.windows(2).map(|s| s[1] - s[0]).collect();
-    diffs_vec.push(sequence);
-    while diffs.iter().any(|&v| v != 0) {
-        let next_diffs: Vec<i32> = diffs.windows(2).map(|s| s[1] - s[0]).collect();
-        diffs_vec.push(diffs);
-        diffs = next_diffs;
+/// A sequence's Newton forward-difference form: the first entry of each row of its difference
+/// table. Evaluating `sum(C(x, j) * leading_diffs[j])` gives the generating polynomial at any
+/// integer `x`, including positions before the start.
+#[derive(Debug)]
+struct NewtonSeries {
+    leading_diffs: Vec<i128>,
+    len: usize,
+    // false if the sequence ran out before a row of differences was all zeros, in which case the
+    // fitted polynomial is only the lowest degree one through the given points
+    reached_zero: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+/// The generating polynomial in `x`, where `x = 0` is the first entry of the sequence.
+#[derive(Debug, PartialEq, Eq)]
+struct FittedPolynomial {
+    // coefficients of x^0, x^1, ... up to the degree
+    coefficients: Vec<Rational>,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+impl Rational {
+    pub fn new(numerator: i128, denominator: i128) -> Rational {
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        Rational { numerator: numerator / divisor, denominator: denominator / divisor }
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+impl FittedPolynomial {
+    /// `None` for the zero polynomial.
+    pub fn degree(&self) -> Option<usize> {
+        self.coefficients.iter().rposition(|c| c.numerator != 0)
+    }
+}
+
+impl Display for FittedPolynomial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut wrote_term = false;
+        for (power, coefficient) in self.coefficients.iter().enumerate().rev() {
+            if coefficient.numerator == 0 {
+                continue;
+            }
+            if wrote_term {
+                write!(f, " + ")?;
+            }
+            match power {
+                0 => write!(f, "{}", coefficient)?,
+                1 => write!(f, "({})x", coefficient)?,
+                _ => write!(f, "({})x^{}", coefficient, power)?,
+            }
+            wrote_term = true;
+        }
+        if !wrote_term {
+            write!(f, "0")?;
+        }
+        Ok(())
+    }
+}
+
+impl NewtonSeries {
+    /// Returns `None` if building the difference table overflows.
+    pub fn from_sequence(sequence: &[i64]) -> Option<NewtonSeries> {
+        let mut row: Vec<i128> = sequence.iter().map(|&v| v as i128).collect();
+        let mut leading_diffs = Vec::new();
+        while row.iter().any(|&v| v != 0) {
+            leading_diffs.push(row[0]);
+            row = row.windows(2).map(|s| s[1].checked_sub(s[0])).collect::<Option<_>>()?;
+        }
+        Some(NewtonSeries { leading_diffs, len: sequence.len(), reached_zero: !row.is_empty() })
+    }
+
+    /// Expands `sum(leading_diffs[j] * C(x, j))` into powers of `x`, or `None` on overflow.
+    pub fn polynomial(&self) -> Option<FittedPolynomial> {
+        let terms = self.leading_diffs.len();
+        if terms == 0 {
+            return Some(FittedPolynomial { coefficients: Vec::new() });
+        }
+        // everything is scaled by (terms - 1)! so C(x, j) = x(x-1)...(x-j+1) / j! stays integral
+        let denominator = (1..terms as i128).try_fold(1i128, |acc, n| acc.checked_mul(n))?;
+        let mut numerators = vec![0i128; terms];
+        // integer coefficients of the falling factorial x(x-1)...(x-j+1), starting with j = 0
+        let mut falling = vec![1i128];
+        let mut factorial = 1i128;
+        for (j, &diff) in self.leading_diffs.iter().enumerate() {
+            if j > 0 {
+                // multiply by (x - (j - 1))
+                let root = j as i128 - 1;
+                let mut next = vec![0i128; falling.len() + 1];
+                for (power, &c) in falling.iter().enumerate() {
+                    next[power + 1] = next[power + 1].checked_add(c)?;
+                    next[power] = next[power].checked_sub(c.checked_mul(root)?)?;
+                }
+                falling = next;
+                factorial *= j as i128;
+            }
+            let scale = diff.checked_mul(denominator / factorial)?;
+            for (numerator, &c) in numerators.iter_mut().zip(falling.iter()) {
+                *numerator = numerator.checked_add(c.checked_mul(scale)?)?;
+            }
+        }
+        let coefficients = numerators.into_iter().map(|n| Rational::new(n, denominator)).collect();
+        Some(FittedPolynomial { coefficients })
+    }
+
+    /// Value of the generating polynomial at position `x`, or `None` on overflow.
+    pub fn value_at(&self, x: i128) -> Option<i128> {
+        let mut value = 0i128;
+        // generalised binomial coefficient C(x, j), exact at every step
+        let mut binomial = 1i128;
+        for (j, &diff) in (0..).zip(self.leading_diffs.iter()) {
+            if j > 0 {
+                binomial = binomial.checked_mul(x - j + 1)? / j;
+            }
+            value = value.checked_add(binomial.checked_mul(diff)?)?;
+        }
+        Some(value)
     }
-    let mut prev_delta = 0;
-    for diffs in diffs_vec.iter_mut().rev() {
-        let next_delta = diffs.first().unwrap() - prev_delta;
-        diffs.insert(0, next_delta);
-        prev_delta = next_delta;
+
+    /// The value `steps` places after the last entry.
+    pub fn forward(&self, steps: usize) -> Option<i128> {
+        self.value_at(self.len as i128 - 1 + steps as i128)
+    }
+
+    /// The value `steps` places before the first entry.
+    pub fn backward(&self, steps: usize) -> Option<i128> {
+        self.value_at(-(steps as i128))
     }
-    *diffs_vec[0].first().unwrap()
 }
 
 pub fn main() {
@@ -32,12 +164,73 @@ pub fn main() {
     let input = read_input("09");
     let (_, sequences) = parse_sequences(&input).unwrap();
 
-    let mut sum = 0;
+    let mut next_sum = 0i128;
+    let mut prev_sum = 0i128;
+    let mut all_series = Vec::with_capacity(sequences.len());
     for sequence in sequences {
-        sum += extrapolate_prev(sequence);
+        let series = NewtonSeries::from_sequence(&sequence).expect("difference table overflowed");
+        next_sum = next_sum.checked_add(series.forward(1).unwrap()).unwrap();
+        prev_sum = prev_sum.checked_add(series.backward(1).unwrap()).unwrap();
+        all_series.push(series);
     }
 
     println!("Total time: {:?}", start_time.elapsed());
-    println!("{}", sum);
+    println!("{}", next_sum);
+    println!("{}", prev_sum);
 
+    for (series, line) in all_series.iter().zip(1..) {
+        match series.polynomial() {
+            Some(polynomial) => {
+                let degree = polynomial.degree().map_or("-".to_string(), |d| d.to_string());
+                let note = if series.reached_zero { "" } else { " (differences never reached zero)" };
+                println!("{}: degree {}: {}{}", line, degree, polynomial, note);
+            },
+            None => println!("{}: polynomial coefficients overflowed", line),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extrapolate() {
+        let series = NewtonSeries::from_sequence(&[10, 13, 16, 21, 30, 45]).unwrap();
+        assert_eq!(series.forward(1), Some(68));
+        assert_eq!(series.backward(1), Some(5));
+        assert_eq!(series.forward(2), Some(101));
+        assert_eq!(series.backward(3), Some(-19));
+        // n^2 from 1 to 4, run far in both directions
+        let series = NewtonSeries::from_sequence(&[1, 4, 9, 16]).unwrap();
+        assert_eq!(series.forward(96), Some(100 * 100));
+        assert_eq!(series.backward(11), Some(100));
+        assert_eq!(NewtonSeries::from_sequence(&[0, 0, 0]).unwrap().backward(5), Some(0));
+    }
+
+    #[test]
+    fn test_polynomial() {
+        // x^2 + 2x + 1
+        let series = NewtonSeries::from_sequence(&[1, 4, 9, 16]).unwrap();
+        let polynomial = series.polynomial().unwrap();
+        assert_eq!(polynomial.coefficients, vec![Rational::new(1, 1), Rational::new(2, 1), Rational::new(1, 1)]);
+        assert_eq!(polynomial.degree(), Some(2));
+        assert!(series.reached_zero);
+        // triangular numbers from 1: (x^2 + 3x + 2) / 2
+        let polynomial = NewtonSeries::from_sequence(&[1, 3, 6, 10, 15, 21]).unwrap().polynomial().unwrap();
+        assert_eq!(polynomial.to_string(), "(1/2)x^2 + (3/2)x + 1");
+        // two points can only pin down a line, so this never reaches a zero row
+        let series = NewtonSeries::from_sequence(&[1, 4]).unwrap();
+        assert!(!series.reached_zero);
+        assert_eq!(series.polynomial().unwrap().degree(), Some(1));
+        assert_eq!(NewtonSeries::from_sequence(&[0, 0]).unwrap().polynomial().unwrap().degree(), None);
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert!(NewtonSeries::from_sequence(&[i64::MAX, i64::MIN]).is_some());
+        let series = NewtonSeries::from_sequence(&[0, 1, 8, 27, 64]).unwrap();
+        assert_eq!(series.forward(usize::MAX), None);
+    }
 }
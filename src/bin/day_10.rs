@@ -1,4 +1,4 @@
-use std::{time::Instant, fmt::Display};
+use std::{time::Instant, fmt::Display, env};
 
 use aoc2023::read_input;
 
@@ -102,12 +102,241 @@ impl Pipe {
     fn has_entrance(self, direction: Direction) -> bool {
         self.entrances().contains(&direction)
     }
+
+    fn box_char(self) -> char {
+        match self {
+            Pipe::None => ' ',
+            Pipe::Start => 'S',
+            Pipe::Vert => '│',
+            Pipe::Horiz => '─',
+            Pipe::NE => '└',
+            Pipe::NW => '┘',
+            Pipe::SE => '┌',
+            Pipe::SW => '┐',
+        }
+    }
+
+    fn from_entrances(first: Direction, second: Direction) -> Pipe {
+        [Pipe::Vert, Pipe::Horiz, Pipe::NE, Pipe::NW, Pipe::SE, Pipe::SW].into_iter()
+            .find(|pipe| first != second && pipe.has_entrance(first) && pipe.has_entrance(second))
+            .unwrap()
+    }
 }
 
-pub fn main() {
-    let start_time = Instant::now();
-    let input = read_input("10");
+fn neighbor(lines: &[Vec<Pipe>], coord: (usize, usize), direction: Direction) -> Option<(usize, usize)> {
+    let (line_delta, col_delta) = direction.to_coord_delta();
+    let adj_line = coord.0.checked_add_signed(line_delta as isize)?;
+    let adj_col = coord.1.checked_add_signed(col_delta as isize)?;
+    if adj_line < lines.len() && adj_col < lines[adj_line].len() {
+        Some((adj_line, adj_col))
+    } else {
+        None
+    }
+}
+
+/// Works out which pipe is hidden under `S` from the neighbouring pipes that connect back to it.
+fn infer_start_pipe(lines: &[Vec<Pipe>], start: (usize, usize)) -> Result<Pipe, String> {
+    let connected: Vec<Direction> = Direction::iter()
+        .filter(|&direction| match neighbor(lines, start, direction) {
+            Some((adj_line, adj_col)) => lines[adj_line][adj_col].has_entrance(direction.invert()),
+            None => false,
+        })
+        .collect();
+    match connected[..] {
+        [first, second] => Ok(Pipe::from_entrances(first, second)),
+        _ => Err(format!("Start at {:?} connects to {:?}, so its pipe is ambiguous", start, connected)),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum InteriorMethod {
+    Crossings,
+    Pick,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Orientation {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// The main loop: its tiles in the order traced from `S`, and the grid with every other pipe
+/// removed and `S` replaced by its real pipe.
+struct PipeLoop {
+    tiles: Vec<(usize, usize)>,
+    cleaned_lines: Vec<Vec<Pipe>>,
+}
+
+#[derive(Debug)]
+struct LoopStats {
+    length: usize,
+    farthest: (usize, usize),
+    farthest_distance: usize,
+    // top-left and bottom-right corners, as (line, column)
+    bounding_box: ((usize, usize), (usize, usize)),
+    // as drawn, with lines running down the screen
+    orientation: Orientation,
+}
+
+/// Twice the signed area enclosed by a closed path of (line, column) points, by the shoelace
+/// formula. Positive when the path runs clockwise on screen.
+fn shoelace(points: &[(usize, usize)]) -> i64 {
+    let mut sum = 0i64;
+    for (&(y1, x1), &(y2, x2)) in points.iter().zip(points.iter().cycle().skip(1)) {
+        sum += x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64;
+    }
+    sum
+}
+
+impl PipeLoop {
+    pub fn trace(lines: &[Vec<Pipe>], start: (usize, usize)) -> Result<PipeLoop, String> {
+        // allocate a vec for the grid with all pipes not part of the loop removed
+        let mut cleaned_lines: Vec<Vec<Pipe>> = Vec::with_capacity(lines.len());
+        for line in lines.iter() {
+            cleaned_lines.push(vec![Pipe::None; line.len()]);
+        }
+        let start_pipe = infer_start_pipe(lines, start)?;
+        cleaned_lines[start.0][start.1] = start_pipe;
+
+        // leave the start through its first entrance, as if we had come in through the second
+        let mut current_coord = start;
+        let mut current_pipe = start_pipe;
+        let mut came_from = start_pipe.entrances()[1];
+        let mut tiles = vec![start];
+
+        loop {
+            let direction = current_pipe.next_entered_from(came_from);
+            let (adj_line, adj_col) = neighbor(lines, current_coord, direction)
+                .ok_or_else(|| format!("Pipe at {:?} leads off the grid", current_coord))?;
+            let adj_pipe = lines[adj_line][adj_col];
+            if !adj_pipe.has_entrance(direction.invert()) {
+                return Err(format!("Pipe {:?} at {:?} could not be entered going {:?} from {:?}", adj_pipe, (adj_line, adj_col), direction, current_coord));
+            }
+            current_coord = (adj_line, adj_col);
+            current_pipe = adj_pipe;
+            came_from = direction.invert();
+
+            if current_coord == start {
+                break;
+            }
+            cleaned_lines[current_coord.0][current_coord.1] = current_pipe;
+            tiles.push(current_coord);
+        }
+
+        Ok(PipeLoop { tiles, cleaned_lines })
+    }
+
+    pub fn stats(&self) -> LoopStats {
+        let length = self.tiles.len();
+        // every loop on a square grid has even length, so the midpoint is the unique farthest tile
+        let farthest_distance = length / 2;
+        let min_line = self.tiles.iter().map(|t| t.0).min().unwrap();
+        let max_line = self.tiles.iter().map(|t| t.0).max().unwrap();
+        let min_col = self.tiles.iter().map(|t| t.1).min().unwrap();
+        let max_col = self.tiles.iter().map(|t| t.1).max().unwrap();
+        let orientation = if shoelace(&self.tiles) > 0 { Orientation::Clockwise } else { Orientation::CounterClockwise };
+        LoopStats {
+            length,
+            farthest: self.tiles[farthest_distance],
+            farthest_distance,
+            bounding_box: ((min_line, min_col), (max_line, max_col)),
+            orientation,
+        }
+    }
+
+    /// Marks enclosed tiles by scanning each line of the cleaned grid and tracking how many
+    /// times the loop has been crossed.
+    pub fn inside_mask(&self) -> Vec<Vec<bool>> {
+        let mut mask = Vec::with_capacity(self.cleaned_lines.len());
+        for line in self.cleaned_lines.iter() {
+            let mut line_mask = vec![false; line.len()];
+            let mut times_crossed = 0;
+            let mut from_south = false;
+            let mut from_north = false;
+            for (pipe, inside) in line.iter().zip(line_mask.iter_mut()).rev() {
+                match pipe {
+                    Pipe::None => {
+                        *inside = times_crossed % 2 == 1;
+                    },
+                    Pipe::Vert => {
+                        times_crossed += 1;
+                    },
+                    Pipe::NW | Pipe::NE => {
+                        if from_south {
+                            times_crossed += 1;
+                            from_south = false;
+                        } else {
+                            from_north = !from_north;
+                        }
+                    },
+                    Pipe::SW | Pipe::SE => {
+                        if from_north {
+                            times_crossed += 1;
+                            from_north = false;
+                        } else {
+                            from_south = !from_south;
+                        }
+                    },
+                    _ => (),
+                }
+            }
+            mask.push(line_mask);
+        }
+        mask
+    }
+
+    pub fn count_inside_crossings(&self) -> usize {
+        self.inside_mask().iter().flatten().filter(|&&inside| inside).count()
+    }
+
+    /// Counts enclosed tiles from the loop's corners alone: the shoelace formula gives the area
+    /// enclosed by the path through tile centres, and Pick's theorem `A = i + b/2 - 1` turns
+    /// that into the number of interior points `i`, with every loop tile a boundary point `b`.
+    pub fn count_inside_pick(&self) -> usize {
+        let vertices: Vec<(usize, usize)> = self.tiles.iter().copied()
+            .filter(|&(line, col)| matches!(self.cleaned_lines[line][col], Pipe::NE | Pipe::NW | Pipe::SE | Pipe::SW))
+            .collect();
+        let double_area = shoelace(&vertices).unsigned_abs() as usize;
+        (double_area + 2 - self.tiles.len()) / 2
+    }
+
+    pub fn count_inside(&self, method: InteriorMethod) -> usize {
+        match method {
+            InteriorMethod::Crossings => self.count_inside_crossings(),
+            InteriorMethod::Pick => self.count_inside_pick(),
+        }
+    }
 
+    /// Draws the cleaned maze with box-drawing characters and ANSI colours: the loop in yellow
+    /// with the start in red, enclosed tiles as green dots and outside tiles as dim dots.
+    pub fn render(&self) -> String {
+        const LOOP: &str = "\x1b[1;33m";
+        const START: &str = "\x1b[1;31m";
+        const INSIDE: &str = "\x1b[32m";
+        const OUTSIDE: &str = "\x1b[2m";
+        const RESET: &str = "\x1b[0m";
+
+        let inside_mask = self.inside_mask();
+        let mut rendered = String::new();
+        for (line_idx, (line, line_mask)) in self.cleaned_lines.iter().zip(inside_mask.iter()).enumerate() {
+            for (col_idx, (&pipe, &inside)) in line.iter().zip(line_mask.iter()).enumerate() {
+                let (colour, symbol) = match pipe {
+                    Pipe::None if inside => (INSIDE, '●'),
+                    Pipe::None => (OUTSIDE, '·'),
+                    _ if (line_idx, col_idx) == self.tiles[0] => (START, pipe.box_char()),
+                    _ => (LOOP, pipe.box_char()),
+                };
+                rendered.push_str(colour);
+                rendered.push(symbol);
+            }
+            rendered.push_str(RESET);
+            rendered.push('\n');
+        }
+        rendered
+    }
+}
+
+fn parse_grid(input: &str) -> (Vec<Vec<Pipe>>, (usize, usize)) {
     let lines: Vec<Vec<Pipe>> = input.lines()
         .map(|l| l.as_bytes().iter().copied().map(Pipe::from_byte).collect()).collect();
 
@@ -120,101 +349,108 @@ pub fn main() {
             }
         }
     }
+    (lines, start)
+}
 
-    // allocate a vec for the grid with all pipes not part of the loop removed
-    let mut cleaned_lines: Vec<Vec<Pipe>> = Vec::with_capacity(lines.len());
-    for line in lines.iter() {
-        cleaned_lines.push(vec![Pipe::None; line.len()]);
-    }
-    cleaned_lines[start.0][start.1] = Pipe::NE;  // known from examining input
-
-    let mut current_coord = start;
-    let mut current_pipe = Pipe::Start;
-    let mut came_from = Direction::South;
-    // find next tile after start
-    for direction in Direction::iter() {
-        let (line_delta, col_delta) = direction.to_coord_delta();
-        let adj_line = start.0.checked_add_signed(line_delta as isize);
-        let adj_col = start.1.checked_add_signed(col_delta as isize);
-        // underflow and edge checking, even though given input has start in middle
-        match (adj_line, adj_col) {
-            (None, _) => continue,
-            (Some(adj_line_idx), _) if adj_line_idx >= lines.len() => continue,
-            (Some(_), None) => continue,
-            (Some(adj_line_idx), Some(adj_col_idx)) if adj_col_idx >= lines[adj_line_idx].len() => continue,
-            (Some(adj_line_idx), Some(adj_col_idx)) => {
-                let adj_pipe = lines[adj_line_idx][adj_col_idx];
-                if adj_pipe.has_entrance(direction.invert()) {
-                    current_coord = (adj_line_idx, adj_col_idx);
-                    current_pipe = adj_pipe;
-                    came_from = direction.invert();
-                    cleaned_lines[current_coord.0][current_coord.1] = current_pipe;
-                    break;
-                }
-            },
-        }
-    }
-    if current_coord == start {
-        panic!("Failed to find next tile")
+pub fn main() {
+    let start_time = Instant::now();
+    let input = read_input("10");
+    // `pick` counts enclosed tiles with the shoelace formula instead of scanning lines, and
+    // `render` draws the classified maze
+    let args: Vec<String> = env::args().skip(1).collect();
+    let method = if args.iter().any(|a| a == "pick") { InteriorMethod::Pick } else { InteriorMethod::Crossings };
+    let render = args.iter().any(|a| a == "render");
+
+    let (lines, start) = parse_grid(&input);
+    let pipe_loop = PipeLoop::trace(&lines, start).unwrap();
+    let stats = pipe_loop.stats();
+    let tiles_inside = pipe_loop.count_inside(method);
+
+    println!("Total time: {:?}", start_time.elapsed());
+    if render {
+        print!("{}", pipe_loop.render());
     }
+    println!("Loop of {} tiles, bounding box {:?} to {:?}, {:?}",
+        stats.length, stats.bounding_box.0, stats.bounding_box.1, stats.orientation);
+    println!("Farthest tile {:?}", stats.farthest);
+    println!("{}", stats.farthest_distance);
+    println!("{}", tiles_inside);
+}
 
-    loop {
-        let direction = current_pipe.next_entered_from(came_from);
-        let (line_delta, col_delta) = direction.to_coord_delta();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let adj_line = current_coord.0.checked_add_signed(line_delta as isize).unwrap();
-        let adj_col = current_coord.1.checked_add_signed(col_delta as isize).unwrap();
-        let adj_pipe = lines[adj_line][adj_col];
-        if !adj_pipe.has_entrance(direction.invert()) {
-            panic!("Pipe {:?} at {:?} could not be entered going {:?} from {:?}", adj_pipe, (adj_line, adj_col), direction, current_coord);
-        }
-        current_coord = (adj_line, adj_col);
-        current_pipe = adj_pipe;
-        came_from = direction.invert();
+    const EXAMPLES: [&str; 6] = [
+        ".....\n.S-7.\n.|.|.\n.L-J.\n.....\n",
+        "..F7.\n.FJ|.\nSJ.L7\n|F--J\nLJ...\n",
+        "...........\n.S-------7.\n.|F-----7|.\n.||.....||.\n.||.....||.\n.|L-7.F-J|.\n.|..|.|..|.\n.L--J.L--J.\n...........\n",
+        "..........\n.S------7.\n.|F----7|.\n.||....||.\n.||....||.\n.|L-7F-J|.\n.|..||..|.\n.L--JL--J.\n..........\n",
+        ".F----7F7F7F7F-7....\n.|F--7||||||||FJ....\n.||.FJ||||||||L7....\nFJL7L7LJLJ||LJ.L-7..\nL--J.L7...LJS7F-7L7.\n....F-J..F7FJ|L7L7L7\n....L7.F7||L7|.L7L7|\n.....|FJLJ|FJ|F7|.LJ\n....FJL-7.||.||||...\n....L---J.LJ.LJLJ...\n",
+        "FF7FSF7F7F7F7F7F---7\nL|LJ||||||||||||F--J\nFL-7LJLJ||||||LJL-77\nF--JF--7||LJLJ7F7FJ-\nL---JF-JLJ.||-FJLJJ7\n|F|F-JF---7F7-L7L|7|\n|FFJF7L7F-JF7|JL---7\n7-L-JL7||F7|L7F-7F7|\nL.L7LFJ|||||FJL7||LJ\nL7JLJL-JLJLJL--JLJ.L\n",
+    ];
 
-        if current_coord == start {
-            break;
-        }
-        cleaned_lines[current_coord.0][current_coord.1] = current_pipe;
-    }
-
-    // count points in cleaned grid that are inside loop
-    let mut tiles_inside = 0;
-    for line in cleaned_lines.iter_mut() {
-        let mut times_crossed = 0;
-        let mut from_south = false;
-        let mut from_north = false;
-        for pipe in line.iter_mut().rev() {
-            match pipe {
-                Pipe::None => {
-                    if times_crossed % 2 == 1 {
-                        tiles_inside += 1;
-                    }
-                },
-                Pipe::Vert => {
-                    times_crossed += 1;
-                },
-                Pipe::NW | Pipe::NE => {
-                    if from_south {
-                        times_crossed += 1;
-                        from_south = false;
-                    } else {
-                        from_north = !from_north;
-                    }
-                },
-                Pipe::SW | Pipe::SE => {
-                    if from_north {
-                        times_crossed += 1;
-                        from_north = false;
-                    } else {
-                        from_south = !from_south;
-                    }
-                },
+    #[test]
+    fn test_infer_start_pipe() {
+        let infer = |grid: &str| {
+            let (lines, start) = parse_grid(grid);
+            infer_start_pipe(&lines, start)
+        };
+        assert_eq!(infer(EXAMPLES[0]), Ok(Pipe::SE));
+        assert_eq!(infer(EXAMPLES[1]), Ok(Pipe::SE));
+        // three neighbours connect to the start
+        assert!(infer("-S-\n.|.\n").is_err());
+        // only one neighbour connects, on the edge of the grid
+        assert!(infer("S-7\n..|\n").is_err());
+        assert!(infer("...\n.S.\n...\n").is_err());
+    }
+
+    #[test]
+    fn test_loop_stats() {
+        let stats = |example: &str| {
+            let (lines, start) = parse_grid(example);
+            PipeLoop::trace(&lines, start).unwrap().stats()
+        };
+        let square = stats(EXAMPLES[0]);
+        assert_eq!((square.length, square.farthest, square.farthest_distance), (8, (3, 3), 4));
+        assert_eq!(square.bounding_box, ((1, 1), (3, 3)));
+        // the trace leaves `S` heading south, which runs anticlockwise on screen
+        assert_eq!(square.orientation, Orientation::CounterClockwise);
+        let winding = stats(EXAMPLES[1]);
+        assert_eq!((winding.length, winding.farthest, winding.farthest_distance), (16, (2, 4), 8));
+        assert_eq!(winding.bounding_box, ((0, 0), (4, 4)));
+        assert_eq!(winding.orientation, Orientation::CounterClockwise);
+    }
+
+    #[test]
+    fn test_render() {
+        let (lines, start) = parse_grid(EXAMPLES[0]);
+        let rendered = PipeLoop::trace(&lines, start).unwrap().render();
+        // with the colour codes stripped, only the box-drawing characters and dots are left
+        let mut plain = String::new();
+        let mut in_escape = false;
+        for c in rendered.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                _ if !in_escape => plain.push(c),
                 _ => (),
             }
         }
+        assert_eq!(plain, "·····\n·┌─┐·\n·│●│·\n·└─┘·\n·····\n");
+        assert!(rendered.contains("\x1b[1;31m┌"));
+        assert_eq!(rendered.matches("\x1b[32m●").count(), 1);
+        assert_eq!(rendered.matches("\x1b[1;33m").count(), 7);
     }
 
-    println!("Total time: {:?}", start_time.elapsed());
-    println!("{}", tiles_inside);
+    #[test]
+    fn test_interior_methods_agree() {
+        let expected = [1, 1, 4, 4, 8, 10];
+        for (example, expected) in EXAMPLES.iter().zip(expected) {
+            let (lines, start) = parse_grid(example);
+            let pipe_loop = PipeLoop::trace(&lines, start).unwrap();
+            assert_eq!(pipe_loop.count_inside(InteriorMethod::Crossings), expected, "{}", example);
+            assert_eq!(pipe_loop.count_inside(InteriorMethod::Pick), expected, "{}", example);
+        }
+    }
 }
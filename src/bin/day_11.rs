@@ -1,61 +1,121 @@
-use std::time::Instant;
+use std::{num::NonZeroUsize, time::Instant};
 
 use aoc2023::read_input;
 
-pub fn main() {
-    let start_time = Instant::now();
-    let input = read_input("11");
+struct Universe {
+    galaxies: Vec<(usize, usize)>,
+    empty_lines: Vec<usize>,
+    empty_cols: Vec<usize>,
+}
 
-    const EXPANSION_FACTOR: usize = 999_999;
-    let mut lines: Vec<Vec<u8>> = input.lines().map(|l| l.as_bytes().to_owned()).collect();
-    let mut empty_lines: Vec<usize> = Vec::new();
-    let mut empty_cols: Vec<usize> = Vec::new();
+impl Universe {
+    fn from_input(input: &str) -> Self {
+        let lines: Vec<&[u8]> = input.lines().map(|l| l.as_bytes()).collect();
+        let mut empty_lines: Vec<usize> = Vec::new();
+        let mut empty_cols: Vec<usize> = Vec::new();
 
-    // find empty lines
-    for i in 0..lines.len() {
-        let line = &mut lines[i];
-        if line.iter().all(|&b| b == b'.') {
-            empty_lines.push(i);
+        // find empty lines
+        for (line_idx, line) in lines.iter().enumerate() {
+            if line.iter().all(|&b| b == b'.') {
+                empty_lines.push(line_idx);
+            }
         }
-    }
 
-    // find empty columns
-    for col_idx in 0..lines[0].len() {
-        if lines.iter().map(|l| l[col_idx]).all(|b| b == b'.') {
-            empty_cols.push(col_idx);
+        // find empty columns
+        for col_idx in 0..lines[0].len() {
+            if lines.iter().map(|l| l[col_idx]).all(|b| b == b'.') {
+                empty_cols.push(col_idx);
+            }
         }
-    }
 
-    // find galaxies
-    let mut galaxies: Vec<(usize, usize)> = Vec::new();
-    for (line_idx, line) in lines.iter().enumerate() {
-        for (col_idx, &byte) in line.iter().enumerate() {
-            if byte == b'#' {
-                let mut galaxy_line = line_idx;
-                let mut galaxy_col = col_idx;
-                galaxy_line += match empty_lines.binary_search(&line_idx) {
-                    Ok(_num_empty) => panic!("This was supposed to be empty"),
-                    Err(num_empty) => num_empty * EXPANSION_FACTOR,
-                };
-                galaxy_col += match empty_cols.binary_search(&col_idx) {
-                    Ok(_num_empty) => panic!("This was supposed to be empty"),
-                    Err(num_empty) => num_empty * EXPANSION_FACTOR,
-                };
-                galaxies.push((galaxy_line, galaxy_col));
+        // find galaxies
+        let mut galaxies: Vec<(usize, usize)> = Vec::new();
+        for (line_idx, line) in lines.iter().enumerate() {
+            for (col_idx, &byte) in line.iter().enumerate() {
+                if byte == b'#' {
+                    galaxies.push((line_idx, col_idx));
+                }
             }
         }
+        Universe { galaxies, empty_lines, empty_cols }
+    }
+
+    /// Galaxy coordinates after every empty line and column has been replaced by
+    /// `expansion_factor` copies of itself.
+    fn expanded_galaxies(&self, expansion_factor: NonZeroUsize) -> Vec<(usize, usize)> {
+        let extra = expansion_factor.get() - 1;
+        self.galaxies.iter().map(|&(line_idx, col_idx)| {
+            let galaxy_line = line_idx + match self.empty_lines.binary_search(&line_idx) {
+                Ok(_num_empty) => panic!("This was supposed to be empty"),
+                Err(num_empty) => num_empty * extra,
+            };
+            let galaxy_col = col_idx + match self.empty_cols.binary_search(&col_idx) {
+                Ok(_num_empty) => panic!("This was supposed to be empty"),
+                Err(num_empty) => num_empty * extra,
+            };
+            (galaxy_line, galaxy_col)
+        }).collect()
     }
-    // compute distances
+
+    /// Sum of Manhattan distances between every pair of galaxies, computed independently
+    /// per axis in O(n log n).
+    fn distance_sum(&self, expansion_factor: NonZeroUsize) -> usize {
+        let galaxies = self.expanded_galaxies(expansion_factor);
+        let mut lines: Vec<usize> = galaxies.iter().map(|g| g.0).collect();
+        let mut cols: Vec<usize> = galaxies.iter().map(|g| g.1).collect();
+        axis_distance_sum(&mut lines) + axis_distance_sum(&mut cols)
+    }
+}
+
+/// Sum of `|a - b|` over all pairs. Once sorted, each coordinate is the larger one in every
+/// pair with the coordinates before it, so it contributes `i * coord - prefix_sum`.
+fn axis_distance_sum(coords: &mut [usize]) -> usize {
+    coords.sort_unstable();
+    let mut prefix_sum = 0;
     let mut sum = 0;
-    for i in 0..galaxies.len() - 1 {
-        let galaxy = galaxies[i];
-        let remaining = &galaxies[i + 1..];
-        for other_galaxy in remaining {
-            let distance = galaxy.0.abs_diff(other_galaxy.0) + galaxy.1.abs_diff(other_galaxy.1);
-            sum += distance;
-        }
+    for (i, &coord) in coords.iter().enumerate() {
+        sum += i * coord - prefix_sum;
+        prefix_sum += coord;
     }
+    sum
+}
+
+pub fn main() {
+    let start_time = Instant::now();
+    let input = read_input("11");
+
+    let universe = Universe::from_input(&input);
+    let part1 = universe.distance_sum(NonZeroUsize::new(2).unwrap());
+    let part2 = universe.distance_sum(NonZeroUsize::new(1_000_000).unwrap());
 
     println!("Total time: {:?}", start_time.elapsed());
-    println!("{}", sum);
+    println!("{}", part1);
+    println!("{}", part2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "...#......
+.......#..
+#.........
+..........
+......#...
+.#........
+.........#
+..........
+.......#..
+#...#.....";
+
+    #[test]
+    fn test_expansion_factors() {
+        let universe = Universe::from_input(EXAMPLE);
+        let distance_sum = |factor| universe.distance_sum(NonZeroUsize::new(factor).unwrap());
+        assert_eq!(distance_sum(2), 374);
+        assert_eq!(distance_sum(10), 1030);
+        assert_eq!(distance_sum(100), 8410);
+        // a factor of 1 leaves the universe as it is
+        assert_eq!(distance_sum(1), 292);
+    }
 }